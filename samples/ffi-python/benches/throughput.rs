@@ -0,0 +1,133 @@
+//! Compares ways of reading a large file for hashing: buffered reads of several sizes, buffered
+//! reads after a `posix_fadvise` sequential hint, and a memory map. Run with
+//!
+//!     cargo bench --bench throughput
+//!
+//! The environment variables below change what is measured:
+//!
+//! * `FILE_CHECKSUM_BENCH_MB`, size of the test file in MiB, 256 by default
+//! * `FILE_CHECKSUM_BENCH_ALGORITHM`, e.g. `MD5` or `BLAKE2b`, SHA256 by default
+//! * `FILE_CHECKSUM_BENCH_ITERATIONS`, runs per strategy of which the best is reported, 3 by default
+//! * `FILE_CHECKSUM_BENCH_COLD`, set to 1 to ask the kernel to drop the file from the page cache
+//!   before every run (Linux only), otherwise the file is hashed from a warm cache
+
+extern crate file_checksum;
+extern crate libc;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use file_checksum::checksum::{self, Algorithm};
+
+fn env_or<T: ::std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+fn make_file(path: &Path, size: usize) {
+    let mut file = File::create(path).unwrap();
+    let block: Vec<u8> = (0..1024 * 1024).map(|i: u32| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+    let mut written = 0;
+    while written < size {
+        let len = block.len().min(size - written);
+        file.write_all(&block[..len]).unwrap();
+        written += len;
+    }
+    file.sync_all().unwrap();
+}
+
+#[cfg(target_os = "linux")]
+fn fadvise(file: &File, advice: libc::c_int) {
+    use std::os::unix::io::AsRawFd;
+    unsafe {
+        libc::posix_fadvise(file.as_raw_fd(), 0, 0, advice);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn drop_cache(path: &Path) {
+    fadvise(&File::open(path).unwrap(), libc::POSIX_FADV_DONTNEED);
+}
+
+#[cfg(not(target_os = "linux"))]
+fn drop_cache(_path: &Path) {}
+
+fn read_with_buffer(path: &Path, algorithm: Algorithm, buffer_size: usize) -> Vec<u8> {
+    let mut file = File::open(path).unwrap();
+    checksum::checksum_reader_with_buffer_size(&mut file, algorithm, buffer_size, |_| true).unwrap().unwrap()
+}
+
+#[cfg(target_os = "linux")]
+fn read_with_fadvise(path: &Path, algorithm: Algorithm) -> Vec<u8> {
+    let mut file = File::open(path).unwrap();
+    fadvise(&file, libc::POSIX_FADV_SEQUENTIAL);
+    checksum::checksum_reader(&mut file, algorithm).unwrap()
+}
+
+fn read_with_mmap(path: &Path, algorithm: Algorithm) -> Vec<u8> {
+    let file = File::open(path).unwrap();
+    checksum::checksum_bytes(&checksum::map_file(&file).unwrap(), algorithm)
+}
+
+type Strategy = Box<dyn Fn() -> Vec<u8>>;
+
+fn main() {
+    // `cargo test --benches` only needs this to build, not to hash hundreds of megabytes
+    if !env::args().any(|arg| arg == "--bench") {
+        return;
+    }
+
+    let size_mb: usize = env_or("FILE_CHECKSUM_BENCH_MB", 256);
+    let algorithm_name: String = env_or("FILE_CHECKSUM_BENCH_ALGORITHM", "SHA256".to_string());
+    let algorithm = Algorithm::from_name(&algorithm_name).expect("unknown algorithm");
+    let iterations: usize = env_or("FILE_CHECKSUM_BENCH_ITERATIONS", 3);
+    let cold = env_or("FILE_CHECKSUM_BENCH_COLD", 0) != 0;
+
+    let path = env::temp_dir().join("file_checksum_throughput.bin");
+    make_file(&path, size_mb * 1024 * 1024);
+
+    let mut strategies: Vec<(String, Strategy)> = Vec::new();
+    for kb in [8, 64, 256, 1024, 4096].iter() {
+        let path = path.clone();
+        strategies.push((format!("read {} KiB", kb), Box::new(move || read_with_buffer(&path, algorithm, kb * 1024))));
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let path = path.clone();
+        strategies.push((
+            format!("read {} KiB + fadvise", checksum::CHUNK_SIZE / 1024),
+            Box::new(move || read_with_fadvise(&path, algorithm)),
+        ));
+    }
+    {
+        let path = path.clone();
+        strategies.push(("mmap".to_string(), Box::new(move || read_with_mmap(&path, algorithm))));
+    }
+
+    println!(
+        "{} MiB, {}, best of {}, {} cache",
+        size_mb,
+        algorithm.name(),
+        iterations,
+        if cold { "cold" } else { "warm" }
+    );
+    let expected = checksum::checksum_file(&path, algorithm).unwrap();
+    for (name, strategy) in strategies.iter() {
+        let mut best = Duration::MAX;
+        for _ in 0..iterations.max(1) {
+            if cold {
+                drop_cache(&path);
+            }
+            let start = Instant::now();
+            let digest = strategy();
+            best = best.min(start.elapsed());
+            assert_eq!(digest, expected, "{} produced a different digest", name);
+        }
+        let throughput = size_mb as f64 / best.as_secs_f64();
+        println!("{:<24} {:>10.1} MiB/s {:>10.3} s", name, throughput, best.as_secs_f64());
+    }
+
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,21 @@
+extern crate cbindgen;
+
+use std::env;
+use std::path::PathBuf;
+
+// Generates include/file_checksum.h from the exported functions and types in src/ so the header
+// can never drift from the library.
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).unwrap();
+
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=src");
+
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("Unable to generate file_checksum.h")
+        .write_to_file(crate_dir.join("include").join("file_checksum.h"));
+}
@@ -0,0 +1,773 @@
+/* Generated by cbindgen from the ffi-python sources, do not edit. */
+
+#ifndef FILE_CHECKSUM_H
+#define FILE_CHECKSUM_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// The version of the C interface. It goes up whenever an export is removed or its signature, a
+// struct layout or an enum value changes, but not when something is added, so a loader built
+// against one version can refuse a library with any other.
+#define FILE_CHECKSUM_ABI_VERSION 2
+
+// The number of entries kept when no cap is given.
+#define FILE_CHECKSUM_DEFAULT_CACHE_ENTRIES 100000
+
+// Length in bytes of the longest digest of any supported algorithm.
+#define FILE_CHECKSUM_MAX_DIGEST_LEN 64
+
+// Length of the longest hex digest of any supported algorithm.
+#define FILE_CHECKSUM_MAX_HEX_DIGEST_LEN 128
+
+// Size of a buffer that holds any hex digest and its NUL terminator.
+#define FILE_CHECKSUM_HEX_DIGEST_BUFFER_LEN (FILE_CHECKSUM_MAX_HEX_DIGEST_LEN + 1)
+
+// Files at least this big are hashed through a memory map by default.
+#define DEFAULT_MMAP_THRESHOLD ((16 * 1024) * 1024)
+
+// Block size used when none is asked for. rsync picks about the square root of the file size;
+// a fixed size suits builds of a few GB well enough.
+#define FILE_CHECKSUM_DEFAULT_BLOCK_SIZE 4096
+
+// Outcome of checking one entry, in the terms `sha256sum -c` reports. The discriminants are part
+// of the C ABI and must not change.
+typedef enum FileChecksumVerifyStatus {
+  FILE_CHECKSUM_VERIFY_STATUS_OK = 0,
+  FILE_CHECKSUM_VERIFY_STATUS_FAILED = 1,
+  FILE_CHECKSUM_VERIFY_STATUS_MISSING = 2,
+  // The file exists but couldn't be read, e.g. for lack of permission.
+  FILE_CHECKSUM_VERIFY_STATUS_UNREADABLE = 3,
+} FileChecksumVerifyStatus;
+
+// The checksum and digest algorithms the library supports. The discriminants are part of the C
+// ABI and must not change.
+typedef enum FileChecksumAlgorithm {
+  FILE_CHECKSUM_ALGORITHM_CRC32 = 0,
+  FILE_CHECKSUM_ALGORITHM_ADLER32 = 1,
+  FILE_CHECKSUM_ALGORITHM_MD5 = 2,
+  FILE_CHECKSUM_ALGORITHM_SHA1 = 3,
+  FILE_CHECKSUM_ALGORITHM_SHA256 = 4,
+  FILE_CHECKSUM_ALGORITHM_SHA512 = 5,
+  FILE_CHECKSUM_ALGORITHM_BLAKE2B = 6,
+} FileChecksumAlgorithm;
+
+// The kinds of archive that can be read. The values are part of the C ABI and must not change.
+typedef enum FileChecksumArchiveFormat {
+  // Work the format out from the first bytes of the file.
+  FILE_CHECKSUM_ARCHIVE_FORMAT_AUTO = 0,
+  FILE_CHECKSUM_ARCHIVE_FORMAT_TAR = 1,
+  // A tar archive compressed with gzip, `.tar.gz` or `.tgz`.
+  FILE_CHECKSUM_ARCHIVE_FORMAT_TAR_GZ = 2,
+  FILE_CHECKSUM_ARCHIVE_FORMAT_ZIP = 3,
+} FileChecksumArchiveFormat;
+
+// How a digest is encoded. The values are part of the C ABI and must not change.
+typedef enum FileChecksumEncoding {
+  // Lowercase hex, as printed by `sha256sum`.
+  FILE_CHECKSUM_ENCODING_HEX_LOWER = 0,
+  // Uppercase hex.
+  FILE_CHECKSUM_ENCODING_HEX_UPPER = 1,
+  // RFC 4648 base64 with padding, as used by Subresource Integrity and `Content-MD5`.
+  FILE_CHECKSUM_ENCODING_BASE64 = 2,
+  // RFC 4648 URL and file name safe base64, without padding.
+  FILE_CHECKSUM_ENCODING_BASE64_URL = 3,
+  // RFC 4648 base32 with padding.
+  FILE_CHECKSUM_ENCODING_BASE32 = 4,
+  // The digest bytes themselves.
+  FILE_CHECKSUM_ENCODING_RAW = 5,
+} FileChecksumEncoding;
+
+// Numeric error codes returned by `file_checksum_last_error_code`. The values are part of the C
+// ABI and must not change.
+typedef enum FileChecksumError {
+  FILE_CHECKSUM_ERROR_OK = 0,
+  FILE_CHECKSUM_ERROR_NULL_ARGUMENT = 1,
+  FILE_CHECKSUM_ERROR_NOT_FOUND = 2,
+  FILE_CHECKSUM_ERROR_PERMISSION_DENIED = 3,
+  FILE_CHECKSUM_ERROR_INVALID_UTF8 = 4,
+  FILE_CHECKSUM_ERROR_IO = 5,
+  FILE_CHECKSUM_ERROR_UNSUPPORTED_ALGORITHM = 6,
+  FILE_CHECKSUM_ERROR_OUT_OF_MEMORY = 7,
+  FILE_CHECKSUM_ERROR_INVALID_HANDLE = 8,
+  FILE_CHECKSUM_ERROR_HANDLE_FINALIZED = 9,
+  FILE_CHECKSUM_ERROR_BUFFER_TOO_SMALL = 10,
+  FILE_CHECKSUM_ERROR_INVALID_MANIFEST = 11,
+  FILE_CHECKSUM_ERROR_INVALID_ARGUMENT = 12,
+  // The library panicked. The call was abandoned but the process can carry on.
+  FILE_CHECKSUM_ERROR_PANIC = 13,
+  // A progress callback asked for the operation to stop.
+  FILE_CHECKSUM_ERROR_CANCELLED = 14,
+  // A signature or delta is damaged, or a delta was applied to the wrong file.
+  FILE_CHECKSUM_ERROR_INVALID_DELTA = 15,
+  // An archive is damaged, truncated or uses a feature that isn't supported.
+  FILE_CHECKSUM_ERROR_INVALID_ARCHIVE = 16,
+} FileChecksumError;
+
+// What kind of file a `ChecksumResult` describes. Symbolic links are followed when the file is
+// opened, so they are never reported. The values are part of the C ABI and must not change.
+typedef enum FileChecksumFileType {
+  // Not known, because the file couldn't be opened or the platform can't tell.
+  FILE_CHECKSUM_FILE_TYPE_UNKNOWN = 0,
+  FILE_CHECKSUM_FILE_TYPE_REGULAR = 1,
+  FILE_CHECKSUM_FILE_TYPE_DIRECTORY = 2,
+  // A named pipe.
+  FILE_CHECKSUM_FILE_TYPE_FIFO = 3,
+  FILE_CHECKSUM_FILE_TYPE_CHAR_DEVICE = 4,
+  FILE_CHECKSUM_FILE_TYPE_BLOCK_DEVICE = 5,
+  FILE_CHECKSUM_FILE_TYPE_SOCKET = 6,
+} FileChecksumFileType;
+
+// How serious a message is, most serious first. The values are part of the C ABI and must not
+// change.
+typedef enum FileChecksumLogLevel {
+  // An exported call failed.
+  FILE_CHECKSUM_LOG_LEVEL_ERROR = 0,
+  // Something didn't go as planned but the call carried on, e.g. a memory map fell back to reads.
+  FILE_CHECKSUM_LOG_LEVEL_WARN = 1,
+  FILE_CHECKSUM_LOG_LEVEL_INFO = 2,
+  // What the library is doing with each file.
+  FILE_CHECKSUM_LOG_LEVEL_DEBUG = 3,
+  FILE_CHECKSUM_LOG_LEVEL_TRACE = 4,
+} FileChecksumLogLevel;
+
+// Layout of a checksum file. The discriminants are part of the C ABI and must not change.
+typedef enum FileChecksumSumFormat {
+  FILE_CHECKSUM_SUM_FORMAT_GNU = 0,
+  FILE_CHECKSUM_SUM_FORMAT_BSD = 1,
+  FILE_CHECKSUM_SUM_FORMAT_SFV = 2,
+} FileChecksumSumFormat;
+
+// Opaque state of an incremental digest, see `checksum_new`. The hasher is taken out when the
+// handle is finalized, after which only `checksum_free` is valid.
+typedef struct ChecksumHandle ChecksumHandle;
+
+// Receives progress from `get_checksum_with_progress`: the bytes hashed so far, the size of the
+// file, or 0 if it isn't known, and the caller's `user_data`. Returning non-zero cancels.
+typedef int (*FileChecksumProgressCallback)(uint64_t processed, uint64_t total, void *user_data);
+
+// Everything about one checksummed file, filled in by `get_checksum_result`. The digest is held
+// inline, only `error_message` lives on the heap and is freed by `release_checksum_result`.
+typedef struct FileChecksumResult {
+  // 0 on success or an `ErrorCode`. On failure only `algorithm` and `error_message` may be set.
+  int status;
+  // One of the `Algorithm` values.
+  int algorithm;
+  // The first `digest_len` bytes are the digest.
+  uint8_t digest[FILE_CHECKSUM_MAX_DIGEST_LEN];
+  size_t digest_len;
+  // Size of the file when it was opened, 0 for anything but a regular file.
+  uint64_t size;
+  // Last modification time in seconds since the Unix epoch, negative before 1970.
+  int64_t mtime_secs;
+  uint32_t mtime_nanos;
+  // Bytes hashed, which differs from `size` if the file changed while it was read.
+  uint64_t bytes_read;
+  // Description of the failure or NULL on success.
+  char *error_message;
+  // One of the `FileType` values.
+  int file_type;
+} FileChecksumResult;
+
+// Describes one of the supported algorithms, see `get_supported_algorithms`.
+typedef struct FileChecksumAlgorithmInfo {
+  // The value to pass as the `algorithm` argument of the exported functions.
+  int algorithm;
+  // Static NUL terminated name of the algorithm, e.g. "SHA256". Must not be freed.
+  const char *name;
+  // Length of the digest in bytes. The hex string is twice as long.
+  size_t digest_len;
+} FileChecksumAlgorithmInfo;
+
+// One file of a `CManifest`.
+typedef struct FileChecksumManifestEntry {
+  // Path relative to the root with `/` separators.
+  char *path;
+  uint64_t size;
+  // Lowercase hex digest.
+  char *digest;
+} FileChecksumManifestEntry;
+
+// The result of `get_directory_manifest`, released with `release_manifest`.
+typedef struct FileChecksumManifest {
+  struct FileChecksumManifestEntry *entries;
+  size_t count;
+} FileChecksumManifest;
+
+// The outcome for one path of `get_checksums_batch`. It is plain data, so an array of results
+// needs no releasing beyond the caller's own.
+typedef struct FileChecksumBatchResult {
+  // 0 if the file was hashed, otherwise an `ErrorCode`.
+  int status;
+  // NUL terminated lowercase hex digest, empty on failure.
+  char digest[FILE_CHECKSUM_HEX_DIGEST_BUFFER_LEN];
+} FileChecksumBatchResult;
+
+// One chunk of a `CChunkList`.
+typedef struct FileChecksumChunk {
+  uint64_t offset;
+  size_t len;
+  // Lowercase hex digest.
+  char *digest;
+} FileChecksumChunk;
+
+// The result of `get_file_chunks`, released with `release_chunk_list`.
+typedef struct FileChecksumChunkList {
+  struct FileChecksumChunk *chunks;
+  size_t count;
+} FileChecksumChunkList;
+
+// Chunking options for the C API, see `ChunkerOptions`.
+typedef struct FileChecksumChunkerOptions {
+  size_t min_size;
+  size_t avg_size;
+  size_t max_size;
+  // One of the `Algorithm` values.
+  int algorithm;
+} FileChecksumChunkerOptions;
+
+// One place a `CSharedChunk` was found.
+typedef struct FileChecksumChunkRef {
+  // Index into the paths passed to `get_shared_chunks`.
+  size_t file;
+  uint64_t offset;
+} FileChecksumChunkRef;
+
+// A chunk found more than once.
+typedef struct FileChecksumSharedChunk {
+  // Lowercase hex digest.
+  char *digest;
+  size_t len;
+  struct FileChecksumChunkRef *refs;
+  size_t ref_count;
+} FileChecksumSharedChunk;
+
+// The result of `get_shared_chunks`, released with `release_dedup_report`.
+typedef struct FileChecksumDedupReport {
+  uint64_t total_bytes;
+  uint64_t unique_bytes;
+  struct FileChecksumSharedChunk *chunks;
+  size_t count;
+} FileChecksumDedupReport;
+
+// One set of identical files in a `CDuplicateReport`.
+typedef struct FileChecksumDuplicateGroup {
+  uint64_t size;
+  uint64_t wasted_bytes;
+  // Lowercase hex digest.
+  char *digest;
+  char **paths;
+  size_t path_count;
+} FileChecksumDuplicateGroup;
+
+// The result of `get_duplicate_files`, released with `release_duplicate_report`.
+typedef struct FileChecksumDuplicateReport {
+  struct FileChecksumDuplicateGroup *groups;
+  size_t count;
+  uint64_t wasted_bytes;
+  size_t files_scanned;
+  size_t files_fully_hashed;
+  // Files left out because they couldn't be read.
+  size_t error_count;
+} FileChecksumDuplicateReport;
+
+// Options for `get_duplicate_files`, see `DuplicateOptions`.
+typedef struct FileChecksumDuplicateOptions {
+  // One of the `Algorithm` values.
+  int algorithm;
+  bool follow_symlinks;
+  bool skip_hidden;
+  // Files smaller than this are ignored. 0 includes empty files.
+  uint64_t min_size;
+} FileChecksumDuplicateOptions;
+
+// Receives a message at `level`, one of the `LogLevel` values, from the part of the library
+// named by `target`, e.g. "cache". Both strings are only valid during the call.
+typedef void (*FileChecksumLogCallback)(int level,
+                                        const char *target,
+                                        const char *message,
+                                        void *user_data);
+
+// Options for `get_directory_manifest`, see `ManifestOptions`.
+typedef struct FileChecksumManifestOptions {
+  // One of the `Algorithm` values.
+  int algorithm;
+  bool follow_symlinks;
+  bool skip_hidden;
+  // Levels of subdirectories to descend into, or a negative value for no limit.
+  int max_depth;
+} FileChecksumManifestOptions;
+
+// One line of a `CVerifyReport`.
+typedef struct FileChecksumVerifyEntry {
+  // The path as written in the checksum file.
+  char *path;
+  enum FileChecksumVerifyStatus status;
+} FileChecksumVerifyEntry;
+
+// The result of `verify_manifest`, released with `release_verify_report`.
+typedef struct FileChecksumVerifyReport {
+  struct FileChecksumVerifyEntry *entries;
+  size_t count;
+} FileChecksumVerifyReport;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Returns the SHA-256 digest of the file at `filepath` as a lowercase hex string, or NULL on
+// failure, in which case `file_checksum_last_error_code` says why. The result must be freed with
+// `release_checksum`.
+//
+// On Unix `filepath` is taken as raw bytes, so names that aren't UTF-8 work. On Windows it must
+// be UTF-8 or the call fails with `InvalidUtf8`.
+//
+// # Safety
+//
+// `filepath` must be NULL or point to a valid NUL terminated string.
+char *get_checksum(const char *filepath);
+
+// Like `get_checksum` but with the digest chosen by `algorithm`, one of the `Algorithm` values.
+// The result must be freed with `release_checksum`.
+//
+// # Safety
+//
+// `filepath` must be NULL or point to a valid NUL terminated string.
+char *get_checksum_with_algorithm(const char *filepath, int algorithm);
+
+// Like `get_checksum_with_algorithm` but with control over the digest cache opened by
+// `file_checksum_cache_open`. When `force` is true the file is always read and the cached digest
+// replaced, otherwise an unchanged file is answered from the cache. Without an open cache it is
+// the same as `get_checksum_with_algorithm`. The result must be freed with `release_checksum`.
+//
+// # Safety
+//
+// `filepath` must be NULL or point to a valid NUL terminated string.
+char *get_checksum_cached(const char *filepath, int algorithm, bool force);
+
+// Like `get_checksum_with_algorithm` with the digest returned as `encoding`, one of the
+// `Encoding` values. The result is always NUL terminated and `*out_len`, if `out_len` isn't
+// NULL, receives its length excluding the terminator. `out_len` is required for `Raw` output,
+// which is the digest bytes themselves and may contain NULs. The result must be freed with
+// `release_checksum`.
+//
+// # Safety
+//
+// `filepath` must be NULL or point to a valid NUL terminated string and `out_len` must be NULL or
+// point to a writable `size_t`.
+char *get_checksum_encoded(const char *filepath, int algorithm, int encoding, size_t *out_len);
+
+// Like `get_checksum` but calls `callback`, if it isn't NULL, once before reading and then after
+// every chunk read, on the calling thread. If the callback returns non-zero the file is closed,
+// NULL is returned and the last error is `Cancelled`. The result must be freed with
+// `release_checksum`.
+//
+// # Safety
+//
+// `filepath` must be NULL or point to a valid NUL terminated string. `user_data` is passed to the
+// callback untouched.
+char *get_checksum_with_progress(const char *filepath,
+                                 FileChecksumProgressCallback callback,
+                                 void *user_data);
+
+// Like `get_checksum_encoded` with the path given as `path_len` bytes, which needn't be NUL
+// terminated or UTF-8. On Unix the bytes are the file name exactly as the file system stores it,
+// e.g. Latin-1 from an older system or a Python `bytes` path. On Windows they must be UTF-8. A NUL
+// byte anywhere in the path fails with `InvalidArgument`. The result must be freed with
+// `release_checksum`.
+//
+// # Safety
+//
+// `filepath` must be NULL or point to at least `path_len` readable bytes and `out_len` must be
+// NULL or point to a writable `size_t`.
+char *get_checksum_path_bytes(const uint8_t *filepath,
+                              size_t path_len,
+                              int algorithm,
+                              int encoding,
+                              size_t *out_len);
+
+// Returns the digest of the `len` bytes at `data` as a lowercase hex string, for data that is
+// already in memory such as a Python `bytes` or `memoryview`. `algorithm` is one of the
+// `Algorithm` values. Returns NULL on failure. The result must be freed with `release_checksum`.
+//
+// # Safety
+//
+// `data` must point to at least `len` readable bytes, or may be NULL if `len` is 0.
+char *checksum_bytes(const uint8_t *data, size_t len, int algorithm);
+
+// Like `checksum_bytes` with the digest returned as `encoding`, see `get_checksum_encoded`. The
+// result must be freed with `release_checksum`.
+//
+// # Safety
+//
+// As for `checksum_bytes`, and `out_len` must be NULL or point to a writable `size_t`.
+char *checksum_bytes_encoded(const uint8_t *data,
+                             size_t len,
+                             int algorithm,
+                             int encoding,
+                             size_t *out_len);
+
+// Returns the digest of everything that can be read from the open file descriptor `fd`, from its
+// current position to the end, as a lowercase hex string. It works for files, pipes, sockets and
+// stdin alike. The descriptor is not closed and is left at the end of the data. `algorithm` is
+// one of the `Algorithm` values. Returns NULL on failure. The result must be freed with
+// `release_checksum`.
+//
+// # Safety
+//
+// `fd` must be an open descriptor that nothing else reads from during the call.
+char *checksum_fd(int fd, int algorithm);
+
+// Like `checksum_fd` with the digest returned as `encoding`, see `get_checksum_encoded`. The
+// result must be freed with `release_checksum`.
+//
+// # Safety
+//
+// As for `checksum_fd`, and `out_len` must be NULL or point to a writable `size_t`.
+char *checksum_fd_encoded(int fd, int algorithm, int encoding, size_t *out_len);
+
+// Writes the SHA-256 digest of the file at `filepath` as a NUL terminated lowercase hex string
+// into the caller's `out_buf` of `out_len` bytes, so nothing needs to be released afterwards.
+// Like `snprintf`, `*written` receives the length of the string excluding the terminator even if
+// the buffer is too small, in which case `BufferTooSmall` is returned and the file isn't read.
+// Pass a NULL `out_buf` and 0 `out_len` to query the size. Returns 0 on success or an
+// `ErrorCode`.
+//
+// # Safety
+//
+// `filepath` must be NULL or point to a valid NUL terminated string, `out_buf` must be NULL or
+// point to at least `out_len` writable bytes and `written` must be NULL or point to a writable
+// `size_t`.
+int get_checksum_into(const char *filepath, char *out_buf, size_t out_len, size_t *written);
+
+// Starts an incremental digest with `algorithm`, one of the `Algorithm` values. Feed it with
+// `checksum_update`, read the result with `checksum_finalize` and always release the handle with
+// `checksum_free`. Returns NULL if the algorithm is unknown.
+struct ChecksumHandle *checksum_new(int algorithm);
+
+// Adds `len` bytes at `data` to the digest. Returns 0 on success or an `ErrorCode`, e.g. if the
+// handle was already finalized or freed.
+//
+// # Safety
+//
+// `data` must point to at least `len` readable bytes, or may be NULL if `len` is 0. The handle
+// must not be used from more than one thread at a time.
+int checksum_update(struct ChecksumHandle *handle, const uint8_t *data, size_t len);
+
+// Finishes the digest and returns it as a lowercase hex string, or NULL on failure. The result
+// must be freed with `release_checksum`. The handle can't be updated afterwards but must still be
+// freed with `checksum_free`.
+//
+// # Safety
+//
+// The handle must not be used from more than one thread at a time.
+char *checksum_finalize(struct ChecksumHandle *handle);
+
+// Releases a handle from `checksum_new`. Returns 0 on success or an `ErrorCode` if the handle is
+// NULL or was already freed.
+//
+// # Safety
+//
+// The handle must not be used from more than one thread at a time.
+int checksum_free(struct ChecksumHandle *handle);
+
+// Checksums the file at `filepath` with `algorithm`, one of the `Algorithm` values, and fills
+// `*result` with the digest and what a `stat` of the open file says, so no second system call or
+// string parsing is needed. The whole file is always read, the digest cache isn't used. Returns
+// the same 0 or `ErrorCode` as is stored in `result->status`. Whatever the outcome, release the
+// result with `release_checksum_result` before it is filled again or discarded.
+//
+// # Safety
+//
+// `filepath` must be NULL or point to a valid NUL terminated string and `result` must be NULL or
+// point to a writable `ChecksumResult`. Its previous contents are overwritten, not released.
+int get_checksum_result(const char *filepath, int algorithm, struct FileChecksumResult *result);
+
+// Frees the heap owned fields of a result filled by `get_checksum_result` and sets them to NULL.
+// The record itself belongs to the caller. Releasing a result twice is harmless.
+//
+// # Safety
+//
+// `result` must be NULL or point to a `ChecksumResult` filled by `get_checksum_result`.
+void release_checksum_result(struct FileChecksumResult *result);
+
+// Writes up to `capacity` entries describing the supported algorithms into `infos` and returns
+// the total number of supported algorithms. Call with a NULL `infos` to find out how many
+// entries to allocate.
+//
+// # Safety
+//
+// `infos` must be NULL or point to at least `capacity` writable `AlgorithmInfo` entries.
+size_t get_supported_algorithms(struct FileChecksumAlgorithmInfo *infos, size_t capacity);
+
+// Frees a string returned by `get_checksum`.
+//
+// # Safety
+//
+// `checksum` must be NULL or a pointer returned by this library that has not been released yet.
+void release_checksum(const char *checksum);
+
+// Sets the size in bytes from which regular files are hashed through a read-only memory map
+// instead of buffered reads, for every thread. 0 turns memory maps off.
+void file_checksum_set_mmap_threshold(uint64_t threshold);
+
+// Returns the current memory map threshold, see `file_checksum_set_mmap_threshold`.
+uint64_t file_checksum_mmap_threshold(void);
+
+// Returns `ABI_VERSION` as the library was built with it, for comparing against the value in the
+// header a program was compiled with.
+uint32_t file_checksum_abi_version(void);
+
+// Returns the `ErrorCode` of the last call into the library made on the calling thread, or 0 if
+// it succeeded.
+int file_checksum_last_error_code(void);
+
+// Returns a description of the last error on the calling thread, or NULL if the last call
+// succeeded. The string is owned by the library and stays valid until the next call into the
+// library on the same thread.
+const char *file_checksum_last_error_message(void);
+
+// Checksums every regular file inside the tar, tar.gz or zip archive at `archive_path` with
+// `algorithm` and returns a manifest of their paths as stored, sizes and digests, in archive
+// order. `format` is one of the `ArchiveFormat` values, `Auto` to recognise it from its content.
+// Nothing is extracted to disk. A damaged archive fails with `InvalidArchive`. Returns NULL on
+// failure. The result must be freed with `release_manifest`.
+//
+// # Safety
+//
+// `archive_path` must be NULL or point to a valid NUL terminated string.
+struct FileChecksumManifest *get_archive_manifest(const char *archive_path,
+                                                  int format,
+                                                  int algorithm);
+
+// Checksums `count` files with SHA-256 on one worker thread per CPU, filling in `results[i]` for
+// `paths[i]`. Returns 0 if the batch ran, even if some files failed, or an `ErrorCode` if the
+// arguments are invalid.
+//
+// # Safety
+//
+// `paths` must point to `count` pointers that are each NULL or a valid NUL terminated string and
+// `results` must point to `count` writable `CBatchResult` entries.
+int get_checksums_batch(const char *const *paths,
+                        size_t count,
+                        struct FileChecksumBatchResult *results);
+
+// Like `get_checksums_batch` with a choice of `algorithm`, one of the `Algorithm` values, and of
+// the maximum number of worker threads. A `workers` of 0 uses one per CPU.
+//
+// # Safety
+//
+// As for `get_checksums_batch`.
+int get_checksums_batch_with_options(const char *const *paths,
+                                     size_t count,
+                                     int algorithm,
+                                     size_t workers,
+                                     struct FileChecksumBatchResult *results);
+
+// Opens the digest cache saved at `path`, creating it on the next flush if it doesn't exist,
+// and routes the path based calls through it. At most `max_entries` digests are kept, or
+// `FILE_CHECKSUM_DEFAULT_CACHE_ENTRIES` if it is 0. A cache that is already open is saved and
+// replaced. Returns 0 or an `ErrorCode`.
+//
+// # Safety
+//
+// `path` must be NULL or point to a valid NUL terminated string.
+int file_checksum_cache_open(const char *path, size_t max_entries);
+
+// Writes the open cache back to its file if it changed. Returns 0 or an `ErrorCode`.
+int file_checksum_cache_flush(void);
+
+// Saves and closes the open cache, after which files are always hashed. Does nothing if no cache
+// is open. Returns 0 or an `ErrorCode`.
+int file_checksum_cache_close(void);
+
+// Forgets the cached digests of the file at `filepath`. Returns 0 or an `ErrorCode`.
+//
+// # Safety
+//
+// `filepath` must be NULL or point to a valid NUL terminated string.
+int file_checksum_cache_invalidate(const char *filepath);
+
+// Forgets every cached digest. Returns 0 or an `ErrorCode`.
+int file_checksum_cache_clear(void);
+
+// Splits the file at `filepath` into content-defined chunks and returns their offsets, lengths
+// and digests, or NULL on failure. A NULL `options` uses 2/8/64 KiB chunks and SHA-256. The
+// result must be freed with `release_chunk_list`.
+//
+// # Safety
+//
+// `filepath` must be NULL or point to a valid NUL terminated string and `options` must be NULL or
+// point to a valid `CChunkerOptions`.
+struct FileChecksumChunkList *get_file_chunks(const char *filepath,
+                                              const struct FileChecksumChunkerOptions *options);
+
+// Frees a list returned by `get_file_chunks`.
+//
+// # Safety
+//
+// `list` must be NULL or a pointer returned by `get_file_chunks` that has not been released yet.
+void release_chunk_list(struct FileChecksumChunkList *list);
+
+// Chunks the `count` files in `paths` on one worker thread per CPU and reports the chunks found
+// more than once, with where each copy is. Returns NULL if any file can't be read. The result
+// must be freed with `release_dedup_report`.
+//
+// # Safety
+//
+// `paths` must point to `count` valid NUL terminated strings and `options` must be NULL or point
+// to a valid `CChunkerOptions`.
+struct FileChecksumDedupReport *get_shared_chunks(const char *const *paths,
+                                                  size_t count,
+                                                  const struct FileChecksumChunkerOptions *options);
+
+// Frees a report returned by `get_shared_chunks`.
+//
+// # Safety
+//
+// `report` must be NULL or a pointer returned by `get_shared_chunks` that has not been released
+// yet.
+void release_dedup_report(struct FileChecksumDedupReport *report);
+
+// Writes the signature of the file at `old_path` to `signature_path`, in blocks of `block_size`
+// bytes, or 4096 if it is 0, with `algorithm` as the strong digest. Returns 0 or an `ErrorCode`.
+//
+// # Safety
+//
+// Both paths must be NULL or point to valid NUL terminated strings.
+int file_checksum_signature(const char *old_path,
+                            const char *signature_path,
+                            size_t block_size,
+                            int algorithm);
+
+// Writes the delta that turns the file described by the signature at `signature_path` into the
+// file at `new_path` to `delta_path`. Returns 0 or an `ErrorCode`.
+//
+// # Safety
+//
+// All paths must be NULL or point to valid NUL terminated strings.
+int file_checksum_delta(const char *signature_path, const char *new_path, const char *delta_path);
+
+// Applies the delta at `delta_path` to the file at `old_path`, which must be the file the
+// signature was made from, writing the result to `out_path`. `out_path` must not be `old_path`.
+// Returns 0, or an `ErrorCode` such as `InvalidDelta` if the delta is damaged or the rebuilt file
+// doesn't match.
+//
+// # Safety
+//
+// All paths must be NULL or point to valid NUL terminated strings.
+int file_checksum_patch(const char *old_path, const char *delta_path, const char *out_path);
+
+// Walks the `count` directories in `roots` and groups the files in them with identical content,
+// biggest waste of space first. A NULL `options` compares every non-empty file with SHA-256.
+// Files that can't be read are left out and counted in `error_count`, but a root that can't be
+// walked fails the call with NULL. The result must be freed with `release_duplicate_report`.
+//
+// # Safety
+//
+// `roots` must point to `count` valid NUL terminated strings and `options` must be NULL or point
+// to a valid `CDuplicateOptions`.
+struct FileChecksumDuplicateReport *get_duplicate_files(const char *const *roots,
+                                                        size_t count,
+                                                        const struct FileChecksumDuplicateOptions *options);
+
+// Frees a report returned by `get_duplicate_files`.
+//
+// # Safety
+//
+// `report` must be NULL or a pointer returned by `get_duplicate_files` that has not been released
+// yet.
+void release_duplicate_report(struct FileChecksumDuplicateReport *report);
+
+// Returns the HMAC of the file at `filepath` keyed with the `key_len` bytes at `key`, as a
+// lowercase hex string. `algorithm` must be `Sha256` or `Sha512`, anything else fails with
+// `UnsupportedAlgorithm`. The library's copies of the key are zeroed before returning, the
+// caller's buffer is left as it is. Returns NULL on failure. The result must be freed with
+// `release_checksum`.
+//
+// # Safety
+//
+// `filepath` must be NULL or point to a valid NUL terminated string and `key` must point to at
+// least `key_len` readable bytes, or may be NULL if `key_len` is 0.
+char *get_checksum_hmac(const char *filepath, int algorithm, const uint8_t *key, size_t key_len);
+
+// Like `get_checksum_hmac` with the result returned as `encoding`, see `get_checksum_encoded`.
+// The result must be freed with `release_checksum`.
+//
+// # Safety
+//
+// As for `get_checksum_hmac`, and `out_len` must be NULL or point to a writable `size_t`.
+char *get_checksum_hmac_encoded(const char *filepath,
+                                int algorithm,
+                                const uint8_t *key,
+                                size_t key_len,
+                                int encoding,
+                                size_t *out_len);
+
+// Registers `callback` to receive every message at `level` or more serious, one of the
+// `LogLevel` values, along with `user_data`. A NULL `callback` removes the current one. Messages
+// come from whichever thread is inside the library, possibly several at once, so the callback
+// must be thread safe. It may call other functions of the library, whose own messages are then
+// dropped, but not this one. Once this returns the previous callback is not running and won't be
+// called again, so its `user_data` can be freed. Returns 0 or an `ErrorCode`.
+//
+// # Safety
+//
+// `callback` must be NULL or a function that is safe to call from any thread with `user_data`
+// until it is replaced or removed.
+int file_checksum_set_log_callback(int level, FileChecksumLogCallback callback, void *user_data);
+
+// Checksums every regular file under the directory `dirpath` and returns the entries sorted by
+// path, or NULL on failure. A NULL `options` hashes everything with SHA-256 without following
+// links. The result must be freed with `release_manifest`.
+//
+// # Safety
+//
+// `dirpath` must be NULL or point to a valid NUL terminated string and `options` must be NULL or
+// point to a valid `CManifestOptions`.
+struct FileChecksumManifest *get_directory_manifest(const char *dirpath,
+                                                    const struct FileChecksumManifestOptions *options);
+
+// Frees a manifest returned by `get_directory_manifest` or `get_archive_manifest`.
+//
+// # Safety
+//
+// `manifest` must be NULL or a pointer returned by `get_directory_manifest` or
+// `get_archive_manifest` that has not been released yet.
+void release_manifest(struct FileChecksumManifest *manifest);
+
+// Checks every entry of the GNU, BSD or SFV checksum file at `path` and reports OK, FAILED,
+// MISSING or UNREADABLE for each, or returns NULL if the checksum file can't be read or parsed.
+// The result must be freed with `release_verify_report`.
+//
+// # Safety
+//
+// `path` must be NULL or point to a valid NUL terminated string.
+struct FileChecksumVerifyReport *verify_manifest(const char *path);
+
+// Frees a report returned by `verify_manifest`.
+//
+// # Safety
+//
+// `report` must be NULL or a pointer returned by `verify_manifest` that has not been released
+// yet.
+void release_verify_report(struct FileChecksumVerifyReport *report);
+
+// Hashes the directory `dirpath` as `get_directory_manifest` does and returns it as the text of
+// a checksum file in `format`, one of the `SumFormat` values. Returns NULL on failure. The result
+// must be freed with `release_checksum`.
+//
+// # Safety
+//
+// `dirpath` must be NULL or point to a valid NUL terminated string and `options` must be NULL or
+// point to a valid `CManifestOptions`.
+char *format_directory_manifest(const char *dirpath,
+                                const struct FileChecksumManifestOptions *options,
+                                int format);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* FILE_CHECKSUM_H */
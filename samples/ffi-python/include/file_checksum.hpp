@@ -0,0 +1,113 @@
+/* RAII wrapper over file_checksum.h for C++11 and later. */
+
+#ifndef FILE_CHECKSUM_HPP
+#define FILE_CHECKSUM_HPP
+
+#include <cstddef>
+#include <memory>
+#include <stdexcept>
+#include <string>
+#include <vector>
+
+#include "file_checksum.h"
+
+namespace file_checksum {
+
+// Thrown when a call into the library fails, carrying the library's error code.
+class Error : public std::runtime_error {
+public:
+    Error(int code, const char *message)
+        : std::runtime_error(message ? message : "file_checksum error"), code_(code) {}
+
+    int code() const { return code_; }
+
+private:
+    int code_;
+};
+
+inline Error last_error() {
+    return Error(file_checksum_last_error_code(), file_checksum_last_error_message());
+}
+
+namespace detail {
+
+struct ChecksumDeleter {
+    void operator()(char *checksum) const { release_checksum(checksum); }
+};
+
+// Takes ownership of a string returned by the library, throwing if it is NULL.
+inline std::string take_string(char *value) {
+    std::unique_ptr<char, ChecksumDeleter> owned(value);
+    if (!owned) {
+        throw last_error();
+    }
+    return std::string(owned.get());
+}
+
+}  // namespace detail
+
+// Returns the digest of the file at `path` as a lowercase hex string.
+inline std::string checksum(const std::string &path,
+                            FileChecksumAlgorithm algorithm = FILE_CHECKSUM_ALGORITHM_SHA256) {
+    return detail::take_string(get_checksum_with_algorithm(path.c_str(), algorithm));
+}
+
+// Returns a description of every supported algorithm.
+inline std::vector<FileChecksumAlgorithmInfo> supported_algorithms() {
+    std::vector<FileChecksumAlgorithmInfo> infos(get_supported_algorithms(NULL, 0));
+    get_supported_algorithms(infos.data(), infos.size());
+    return infos;
+}
+
+// Owns a `ChecksumHandle` for incremental hashing, freeing it when it goes out of scope.
+class Hasher {
+public:
+    explicit Hasher(FileChecksumAlgorithm algorithm = FILE_CHECKSUM_ALGORITHM_SHA256)
+        : handle_(checksum_new(algorithm)) {
+        if (!handle_) {
+            throw last_error();
+        }
+    }
+
+    Hasher(Hasher &&other) noexcept : handle_(other.handle_) { other.handle_ = NULL; }
+
+    Hasher &operator=(Hasher &&other) noexcept {
+        if (this != &other) {
+            reset();
+            handle_ = other.handle_;
+            other.handle_ = NULL;
+        }
+        return *this;
+    }
+
+    Hasher(const Hasher &) = delete;
+    Hasher &operator=(const Hasher &) = delete;
+
+    ~Hasher() { reset(); }
+
+    Hasher &update(const void *data, size_t len) {
+        if (checksum_update(handle_, static_cast<const uint8_t *>(data), len) != FILE_CHECKSUM_ERROR_OK) {
+            throw last_error();
+        }
+        return *this;
+    }
+
+    Hasher &update(const std::string &data) { return update(data.data(), data.size()); }
+
+    // Returns the digest as a lowercase hex string. The hasher can't be updated afterwards.
+    std::string finalize() { return detail::take_string(checksum_finalize(handle_)); }
+
+private:
+    void reset() {
+        if (handle_) {
+            checksum_free(handle_);
+            handle_ = NULL;
+        }
+    }
+
+    ChecksumHandle *handle_;
+};
+
+}  // namespace file_checksum
+
+#endif /* FILE_CHECKSUM_HPP */
@@ -0,0 +1,374 @@
+//! Checksumming the members of tar, gzip compressed tar and zip archives without extracting them.
+//! Each regular file in the archive is streamed through the digest straight out of the archive,
+//! and each hard link takes the digest of the member it links to, giving the same manifest
+//! entries `hash_directory` would for the extracted tree, in the order the members are stored.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use libc::{c_char, c_int};
+
+use flate2::read::GzDecoder;
+use tar;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use checksum::{self, Algorithm};
+use error::{self, Error, ErrorCode};
+use ffi::{algorithm_from_c, path_from_c};
+use manifest::{self, CManifest, ManifestEntry};
+
+/// The kinds of archive that can be read. The values are part of the C ABI and must not change.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArchiveFormat {
+    /// Work the format out from the first bytes of the file.
+    #[default]
+    Auto = 0,
+    Tar = 1,
+    /// A tar archive compressed with gzip, `.tar.gz` or `.tgz`.
+    TarGz = 2,
+    Zip = 3,
+}
+
+impl ArchiveFormat {
+    /// cbindgen:ignore
+    pub const ALL: [ArchiveFormat; 4] = [ArchiveFormat::Auto, ArchiveFormat::Tar, ArchiveFormat::TarGz, ArchiveFormat::Zip];
+
+    /// Maps the integer passed across the C ABI back to a format.
+    pub fn from_raw(value: i32) -> Option<ArchiveFormat> {
+        ArchiveFormat::ALL.iter().cloned().find(|f| *f as i32 == value)
+    }
+
+    /// Recognises an archive from its first bytes. Anything that isn't gzip or zip is taken to be
+    /// tar, since old tar archives have no magic number to look for.
+    pub fn detect(header: &[u8]) -> ArchiveFormat {
+        if header.starts_with(b"\x1f\x8b") {
+            ArchiveFormat::TarGz
+        } else if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+            ArchiveFormat::Zip
+        } else {
+            ArchiveFormat::Tar
+        }
+    }
+}
+
+fn invalid_archive<S: Into<String>>(message: S) -> Error {
+    Error::new(ErrorCode::InvalidArchive, message)
+}
+
+/// An error reading the archive file itself, as opposed to one a decoder raised about its content.
+#[derive(Debug)]
+struct FileError(io::Error);
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl ::std::error::Error for FileError {}
+
+/// Wraps the archive file so its own errors reach `read_error` recognisably through the decoders.
+struct TaggedFile(File);
+
+impl Read for TaggedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf).map_err(|err| io::Error::new(err.kind(), FileError(err)))
+    }
+}
+
+impl Seek for TaggedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos).map_err(|err| io::Error::new(err.kind(), FileError(err)))
+    }
+}
+
+/// Errors from reading the archive file are real I/O errors; anything else was raised by a decoder
+/// (the tar crate reports a truncated archive as `ErrorKind::Other`) and is `InvalidArchive`.
+fn read_error(err: io::Error) -> Error {
+    if err.get_ref().is_some_and(|inner| inner.is::<FileError>()) {
+        let FileError(err) = *err.into_inner().unwrap().downcast::<FileError>().unwrap();
+        Error::from(err)
+    } else {
+        invalid_archive(err.to_string())
+    }
+}
+
+/// Returns the path, size and digest of every regular file in the tar archive `reader` yields.
+/// A hard link is listed with the size and digest of the earlier member it links to, since it
+/// becomes a copy of that file when extracted. Directories, symbolic links and devices are skipped.
+pub fn hash_tar<R: Read>(reader: R, algorithm: Algorithm) -> Result<Vec<ManifestEntry>, Error> {
+    let mut archive = tar::Archive::new(reader);
+    let mut entries: Vec<ManifestEntry> = Vec::new();
+    // Where each regular file's entry is, by path as stored, for the hard links to it
+    let mut files: HashMap<Vec<u8>, usize> = HashMap::new();
+    for member in archive.entries().map_err(read_error)? {
+        let mut member = member.map_err(read_error)?;
+        let entry_type = member.header().entry_type();
+        let path_bytes = member.path_bytes().into_owned();
+        let path = member_path(&path_bytes)?;
+        if entry_type.is_hard_link() {
+            let target = member.link_name_bytes().map(|target| target.into_owned()).unwrap_or_default();
+            let linked = files.get(&target).map(|i| &entries[*i]).ok_or_else(|| {
+                invalid_archive(format!("{} is a hard link to {}, which isn't an earlier file in the archive", path.display(), String::from_utf8_lossy(&target)))
+            })?;
+            let (size, digest) = (linked.size, linked.digest.clone());
+            files.insert(path_bytes, entries.len());
+            entries.push(ManifestEntry { path, size, digest });
+            continue;
+        }
+        if !entry_type.is_file() && !entry_type.is_contiguous() {
+            continue;
+        }
+        let size = member.size();
+        let digest = checksum::checksum_reader(&mut member, algorithm).map_err(read_error)?;
+        files.insert(path_bytes, entries.len());
+        entries.push(ManifestEntry { path, size, digest });
+    }
+    Ok(entries)
+}
+
+/// The path of a member as stored. A NUL can't be part of a file name, nor be passed back to C.
+fn member_path(name: &[u8]) -> Result<PathBuf, Error> {
+    if name.contains(&0) {
+        return Err(invalid_archive(format!("member name {:?} contains a NUL byte", String::from_utf8_lossy(name))));
+    }
+    Ok(manifest::path_from_bytes(name))
+}
+
+fn zip_error(err: ZipError) -> Error {
+    match err {
+        ZipError::Io(err) => read_error(err),
+        err => invalid_archive(err.to_string()),
+    }
+}
+
+/// Returns the path, size and digest of every file in the zip archive `reader` yields. Members
+/// are decompressed as they are hashed and their CRC-32 checked. Directories and links are
+/// skipped, and encrypted members fail with `InvalidArchive`.
+pub fn hash_zip<R: Read + Seek>(reader: R, algorithm: Algorithm) -> Result<Vec<ManifestEntry>, Error> {
+    let mut archive = ZipArchive::new(reader).map_err(zip_error)?;
+    let mut entries = Vec::new();
+    for i in 0..archive.len() {
+        let mut member = archive.by_index(i).map_err(zip_error)?;
+        if !member.is_file() {
+            continue;
+        }
+        let path = member_path(member.name_raw())?;
+        let size = member.size();
+        let digest = checksum::checksum_reader(&mut member, algorithm).map_err(read_error)?;
+        entries.push(ManifestEntry { path, size, digest });
+    }
+    Ok(entries)
+}
+
+/// Checksums every regular file inside the archive at `path`, in `format` or whatever format it
+/// turns out to be for `Auto`. Member paths are as stored in the archive.
+pub fn hash_archive<P: AsRef<Path>>(path: P, format: ArchiveFormat, algorithm: Algorithm) -> Result<Vec<ManifestEntry>, Error> {
+    let path = path.as_ref();
+    let with_path = |err: Error| Error::new(err.code, format!("{}: {}", path.to_string_lossy(), err.message));
+    let mut file = File::open(path).map_err(|err| Error::from_io(&err, &path.to_string_lossy()))?;
+    let format = match format {
+        ArchiveFormat::Auto => {
+            let mut header = Vec::with_capacity(4);
+            (&mut file).take(4).read_to_end(&mut header).map_err(Error::from).map_err(with_path)?;
+            file.seek(SeekFrom::Start(0)).map_err(Error::from).map_err(with_path)?;
+            ArchiveFormat::detect(&header)
+        }
+        format => format,
+    };
+    let file = BufReader::new(TaggedFile(file));
+    match format {
+        ArchiveFormat::Tar | ArchiveFormat::Auto => hash_tar(file, algorithm),
+        ArchiveFormat::TarGz => hash_tar(GzDecoder::new(file), algorithm),
+        ArchiveFormat::Zip => hash_zip(file, algorithm),
+    }
+    .map_err(with_path)
+}
+
+/// Checksums every regular file inside the tar, tar.gz or zip archive at `archive_path` with
+/// `algorithm` and returns a manifest of their paths as stored, sizes and digests, in archive
+/// order. `format` is one of the `ArchiveFormat` values, `Auto` to recognise it from its content.
+/// Nothing is extracted to disk. A damaged archive fails with `InvalidArchive`. Returns NULL on
+/// failure. The result must be freed with `release_manifest`.
+///
+/// # Safety
+///
+/// `archive_path` must be NULL or point to a valid NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn get_archive_manifest(archive_path: *const c_char, format: c_int, algorithm: c_int) -> *mut CManifest {
+    error::guard(ptr::null_mut(), || {
+        let archive_path = path_from_c(archive_path)?;
+        let format = ArchiveFormat::from_raw(format)
+            .ok_or_else(|| Error::new(ErrorCode::InvalidArgument, format!("unsupported archive format {}", format)))?;
+        hash_archive(archive_path, format, algorithm_from_c(algorithm)?).map(manifest::to_c_manifest)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::ffi::{CStr, CString};
+    use std::fs;
+    use std::io::{Cursor, Write};
+    use std::slice;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use zip::write::SimpleFileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    use manifest::release_manifest;
+
+    const MEMBERS: [(&str, &[u8]); 3] = [("readme.txt", b"abc"), ("dir/data.bin", &[0u8; 100_000]), ("dir/empty", b"")];
+
+    fn expected(algorithm: Algorithm) -> Vec<ManifestEntry> {
+        MEMBERS
+            .iter()
+            .map(|(name, data)| ManifestEntry { path: PathBuf::from(name), size: data.len() as u64, digest: checksum::checksum_bytes(data, algorithm) })
+            .collect()
+    }
+
+    fn tar_bytes() -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut dir = tar::Header::new_gnu();
+        dir.set_entry_type(tar::EntryType::Directory);
+        dir.set_size(0);
+        builder.append_data(&mut dir, "dir/", io::empty()).unwrap();
+        for (name, data) in MEMBERS.iter() {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        let mut link = tar::Header::new_gnu();
+        link.set_entry_type(tar::EntryType::Symlink);
+        link.set_size(0);
+        builder.append_link(&mut link, "link", "readme.txt").unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    fn zip_bytes() -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.add_directory("dir/", SimpleFileOptions::default()).unwrap();
+        for (i, (name, data)) in MEMBERS.iter().enumerate() {
+            let method = if i % 2 == 0 { CompressionMethod::Deflated } else { CompressionMethod::Stored };
+            writer.start_file(*name, SimpleFileOptions::default().compression_method(method)).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn write_archive(name: &str, data: &[u8]) -> PathBuf {
+        let path = temp_dir().join(name);
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn members_of_each_format() {
+        let tar = tar_bytes();
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&tar).unwrap();
+        let archives = [
+            (write_archive("file_checksum_archive.tar", &tar), ArchiveFormat::Tar),
+            (write_archive("file_checksum_archive.tar.gz", &gz.finish().unwrap()), ArchiveFormat::TarGz),
+            (write_archive("file_checksum_archive.zip", &zip_bytes()), ArchiveFormat::Zip),
+        ];
+        for (path, format) in archives.iter() {
+            assert_eq!(hash_archive(path, *format, Algorithm::Sha256).unwrap(), expected(Algorithm::Sha256), "{:?}", format);
+            assert_eq!(hash_archive(path, ArchiveFormat::Auto, Algorithm::Md5).unwrap(), expected(Algorithm::Md5), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn hard_links_take_their_targets_digest() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(3);
+        builder.append_data(&mut header, "readme.txt", &b"abc"[..]).unwrap();
+        let mut link = tar::Header::new_gnu();
+        link.set_entry_type(tar::EntryType::Link);
+        link.set_size(0);
+        builder.append_link(&mut link, "dir/hard", "readme.txt").unwrap();
+        builder.append_link(&mut link, "dir/hard again", "dir/hard").unwrap();
+        let tar = builder.into_inner().unwrap();
+
+        let entries = hash_tar(&tar[..], Algorithm::Sha256).unwrap();
+        let digest = checksum::checksum_bytes(b"abc", Algorithm::Sha256);
+        assert_eq!(
+            entries,
+            ["readme.txt", "dir/hard", "dir/hard again"]
+                .iter()
+                .map(|name| ManifestEntry { path: PathBuf::from(name), size: 3, digest: digest.clone() })
+                .collect::<Vec<_>>()
+        );
+
+        let mut builder = tar::Builder::new(Vec::new());
+        builder.append_link(&mut link, "dangling", "missing").unwrap();
+        let tar = builder.into_inner().unwrap();
+        assert_eq!(hash_tar(&tar[..], Algorithm::Sha256).unwrap_err().code, ErrorCode::InvalidArchive);
+    }
+
+    #[test]
+    fn damaged_archives() {
+        let mut zip = zip_bytes();
+        // Flip a byte of the stored member's data so its CRC-32 no longer matches
+        let at = zip.windows(MEMBERS[1].0.len()).position(|w| w == MEMBERS[1].0.as_bytes()).unwrap() + MEMBERS[1].0.len() + 10;
+        zip[at] ^= 1;
+        let path = write_archive("file_checksum_archive_bad.zip", &zip);
+        let err = hash_archive(&path, ArchiveFormat::Auto, Algorithm::Sha256).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidArchive);
+        assert!(err.message.starts_with(path.to_str().unwrap()), "{}", err.message);
+
+        let tar = tar_bytes();
+        let path = write_archive("file_checksum_archive_short.tar", &tar[..1000]);
+        assert_eq!(hash_archive(&path, ArchiveFormat::Tar, Algorithm::Sha256).unwrap_err().code, ErrorCode::InvalidArchive);
+        assert_eq!(hash_archive(&path, ArchiveFormat::Zip, Algorithm::Sha256).unwrap_err().code, ErrorCode::InvalidArchive);
+        assert_eq!(hash_archive(&path, ArchiveFormat::TarGz, Algorithm::Sha256).unwrap_err().code, ErrorCode::InvalidArchive);
+        assert_eq!(hash_archive("/this/path/does/not/exist", ArchiveFormat::Auto, Algorithm::Sha256).unwrap_err().code, ErrorCode::NotFound);
+        // Failing to read the file itself is not a damaged archive
+        assert_eq!(hash_archive(temp_dir(), ArchiveFormat::Tar, Algorithm::Sha256).unwrap_err().code, ErrorCode::Io);
+    }
+
+    #[test]
+    fn nul_in_member_name() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file("bad\0name", SimpleFileOptions::default()).unwrap();
+        writer.write_all(b"abc").unwrap();
+        let path = write_archive("file_checksum_archive_nul.zip", &writer.finish().unwrap().into_inner());
+        let err = hash_archive(&path, ArchiveFormat::Auto, Algorithm::Sha256).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidArchive);
+
+        let archive_path = CString::new(path.to_str().unwrap()).unwrap();
+        unsafe {
+            assert!(get_archive_manifest(archive_path.as_ptr(), ArchiveFormat::Zip as c_int, Algorithm::Sha256 as c_int).is_null());
+        }
+        assert_eq!(error::last_error_code(), ErrorCode::InvalidArchive);
+    }
+
+    #[test]
+    fn c_api() {
+        let path = write_archive("file_checksum_archive_c.zip", &zip_bytes());
+        let archive_path = CString::new(path.to_str().unwrap()).unwrap();
+        unsafe {
+            let manifest = get_archive_manifest(archive_path.as_ptr(), ArchiveFormat::Auto as c_int, Algorithm::Sha1 as c_int);
+            assert!(!manifest.is_null());
+            let entries = slice::from_raw_parts((*manifest).entries, (*manifest).count);
+            assert_eq!(entries.len(), 3);
+            assert_eq!(CStr::from_ptr(entries[1].path).to_str().unwrap(), "dir/data.bin");
+            assert_eq!(entries[1].size, 100_000);
+            assert_eq!(CStr::from_ptr(entries[1].digest).to_str().unwrap(), checksum::to_hex(&checksum::checksum_bytes(MEMBERS[1].1, Algorithm::Sha1)));
+            release_manifest(manifest);
+
+            assert!(get_archive_manifest(archive_path.as_ptr(), 7, Algorithm::Sha1 as c_int).is_null());
+            assert_eq!(error::last_error_code(), ErrorCode::InvalidArgument);
+            assert!(get_archive_manifest(archive_path.as_ptr(), ArchiveFormat::Zip as c_int, 99).is_null());
+            assert_eq!(error::last_error_code(), ErrorCode::UnsupportedAlgorithm);
+        }
+    }
+}
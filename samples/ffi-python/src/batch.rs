@@ -0,0 +1,213 @@
+//! Checksumming many files at once on a bounded pool of worker threads. Results are always
+//! returned in the order of the input paths, whichever worker finished first.
+
+use std::path::Path;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use libc::{c_char, c_int};
+
+use cache;
+use checksum::{self, Algorithm, HEX_DIGEST_BUFFER_LEN};
+use error::{self, Error, ErrorCode};
+use ffi::{algorithm_from_c, path_from_c};
+
+/// The number of workers used when none is asked for, one per CPU.
+pub fn default_workers() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// Runs `f` on every item using at most `workers` threads, returning the results in item order.
+/// A `workers` of 0 means `default_workers()`.
+pub fn run_pool<T, R, F>(items: &[T], workers: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let workers = if workers == 0 { default_workers() } else { workers };
+    let workers = workers.min(items.len());
+    if workers <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let (next, f) = (&next, &f);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                if index >= items.len() {
+                    break;
+                }
+                // The receiver outlives the scope so this can't fail
+                let _ = sender.send((index, f(&items[index])));
+            });
+        }
+    });
+    drop(sender);
+
+    let mut results: Vec<Option<R>> = (0..items.len()).map(|_| None).collect();
+    for (index, result) in receiver {
+        results[index] = Some(result);
+    }
+    results.into_iter().map(|r| r.expect("worker skipped an item")).collect()
+}
+
+/// Checksums every file in `paths` on up to `workers` threads. Each file succeeds or fails on its
+/// own.
+pub fn checksum_files<P: AsRef<Path> + Sync>(paths: &[P], algorithm: Algorithm, workers: usize) -> Vec<Result<Vec<u8>, Error>> {
+    run_pool(paths, workers, |path| {
+        let path = path.as_ref();
+        cache::checksum_file(path, algorithm, false).map_err(|err| Error::from_io(&err, &path.to_string_lossy()))
+    })
+}
+
+/// The outcome for one path of `get_checksums_batch`. It is plain data, so an array of results
+/// needs no releasing beyond the caller's own.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct CBatchResult {
+    /// 0 if the file was hashed, otherwise an `ErrorCode`.
+    pub status: c_int,
+    /// NUL terminated lowercase hex digest, empty on failure.
+    pub digest: [c_char; HEX_DIGEST_BUFFER_LEN],
+}
+
+unsafe fn checksum_batch(paths: *const *const c_char, count: usize, algorithm: c_int, workers: usize, results: *mut CBatchResult) -> Result<(), Error> {
+    let algorithm = algorithm_from_c(algorithm)?;
+    if count == 0 {
+        return Ok(());
+    }
+    if paths.is_null() || results.is_null() {
+        return Err(Error::new(ErrorCode::NullArgument, "paths or results is NULL"));
+    }
+
+    // Raw pointers can't be shared with the workers so the paths are borrowed up front
+    let paths: Vec<Result<&Path, Error>> = slice::from_raw_parts(paths, count).iter().map(|p| path_from_c(*p)).collect();
+    let digests = run_pool(&paths, workers, |path| {
+        let path = path.clone()?;
+        cache::checksum_file(path, algorithm, false).map_err(|err| Error::from_io(&err, &path.to_string_lossy()))
+    });
+
+    let results = slice::from_raw_parts_mut(results, count);
+    for (result, digest) in results.iter_mut().zip(digests) {
+        result.digest = [0; HEX_DIGEST_BUFFER_LEN];
+        match digest {
+            Ok(digest) => {
+                result.status = ErrorCode::Ok as c_int;
+                for (dst, src) in result.digest.iter_mut().zip(checksum::to_hex(&digest).bytes()) {
+                    *dst = src as c_char;
+                }
+            }
+            Err(err) => result.status = err.code as c_int,
+        }
+    }
+    Ok(())
+}
+
+/// Checksums `count` files with SHA-256 on one worker thread per CPU, filling in `results[i]` for
+/// `paths[i]`. Returns 0 if the batch ran, even if some files failed, or an `ErrorCode` if the
+/// arguments are invalid.
+///
+/// # Safety
+///
+/// `paths` must point to `count` pointers that are each NULL or a valid NUL terminated string and
+/// `results` must point to `count` writable `CBatchResult` entries.
+#[no_mangle]
+pub unsafe extern "C" fn get_checksums_batch(paths: *const *const c_char, count: usize, results: *mut CBatchResult) -> c_int {
+    get_checksums_batch_with_options(paths, count, Algorithm::default() as c_int, 0, results)
+}
+
+/// Like `get_checksums_batch` with a choice of `algorithm`, one of the `Algorithm` values, and of
+/// the maximum number of worker threads. A `workers` of 0 uses one per CPU.
+///
+/// # Safety
+///
+/// As for `get_checksums_batch`.
+#[no_mangle]
+pub unsafe extern "C" fn get_checksums_batch_with_options(paths: *const *const c_char, count: usize, algorithm: c_int, workers: usize, results: *mut CBatchResult) -> c_int {
+    error::guard_status(|| checksum_batch(paths, count, algorithm, workers, results)) as c_int
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::ffi::{CStr, CString};
+    use std::path::PathBuf;
+    use std::ptr;
+
+    use test_util::make_tree;
+
+    fn make_files(name: &str, count: usize) -> Vec<PathBuf> {
+        let files: Vec<(String, String)> = (0..count).map(|i| (format!("{}.txt", i), format!("file {}", i).repeat(i + 1))).collect();
+        let tree: Vec<(&str, &[u8])> = files.iter().map(|(path, data)| (path.as_str(), data.as_bytes())).collect();
+        let dir = make_tree(temp_dir().join(name), &tree);
+        files.iter().map(|(path, _)| dir.join(path)).collect()
+    }
+
+    #[test]
+    fn pool_keeps_order() {
+        let items: Vec<usize> = (0..1000).collect();
+        for workers in [0, 1, 3, 64].iter() {
+            assert_eq!(run_pool(&items, *workers, |i| i * 2), items.iter().map(|i| i * 2).collect::<Vec<_>>());
+        }
+        assert!(run_pool(&[] as &[usize], 4, |i| *i).is_empty());
+    }
+
+    #[test]
+    fn worker_panic_is_caught() {
+        // A panic on a worker thread resurfaces on the calling thread, where the export's guard
+        // turns it into an error
+        let items: Vec<usize> = (0..100).collect();
+        let result = error::guard(0, || Ok(run_pool(&items, 4, |i| if *i == 42 { panic!("worker {}", i) } else { *i }).len()));
+        assert_eq!(result, 0);
+        assert_eq!(error::last_error_code(), ErrorCode::Panic);
+        assert_eq!(run_pool(&items, 4, |i| *i).len(), 100);
+    }
+
+    #[test]
+    fn batch_matches_single_files() {
+        let mut paths = make_files("file_checksum_batch", 20);
+        paths.insert(7, PathBuf::from("/this/path/does/not/exist"));
+        let results = checksum_files(&paths, Algorithm::Md5, 4);
+        assert_eq!(results.len(), paths.len());
+        for (path, result) in paths.iter().zip(results) {
+            match checksum::checksum_file(path, Algorithm::Md5) {
+                Ok(digest) => assert_eq!(result.unwrap(), digest),
+                Err(_) => assert_eq!(result.unwrap_err().code, ErrorCode::NotFound),
+            }
+        }
+    }
+
+    #[test]
+    fn c_batch() {
+        let files = make_files("file_checksum_c_batch", 5);
+        let mut paths: Vec<CString> = files.iter().map(|p| CString::new(p.to_str().unwrap()).unwrap()).collect();
+        paths.push(CString::new("/this/path/does/not/exist").unwrap());
+        let mut pointers: Vec<*const c_char> = paths.iter().map(|p| p.as_ptr()).collect();
+        pointers.push(ptr::null());
+
+        let empty = CBatchResult { status: -1, digest: [1; HEX_DIGEST_BUFFER_LEN] };
+        let mut results = vec![empty; pointers.len()];
+        unsafe {
+            let status = get_checksums_batch_with_options(pointers.as_ptr(), pointers.len(), Algorithm::Sha512 as c_int, 2, results.as_mut_ptr());
+            assert_eq!(status, 0);
+            for (file, result) in files.iter().zip(results.iter()) {
+                let expected = checksum::to_hex(&checksum::checksum_file(file, Algorithm::Sha512).unwrap());
+                assert_eq!(result.status, 0);
+                assert_eq!(CStr::from_ptr(result.digest.as_ptr()).to_str().unwrap(), expected);
+            }
+            assert_eq!(results[5].status, ErrorCode::NotFound as c_int);
+            assert_eq!(results[5].digest[0], 0);
+            assert_eq!(results[6].status, ErrorCode::NullArgument as c_int);
+
+            assert_eq!(get_checksums_batch(ptr::null(), 1, results.as_mut_ptr()), ErrorCode::NullArgument as c_int);
+            assert_eq!(get_checksums_batch(ptr::null(), 0, ptr::null_mut()), 0);
+        }
+    }
+}
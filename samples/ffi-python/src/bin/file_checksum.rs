@@ -0,0 +1,326 @@
+//! `file-checksum`, a command line front end to the same hashing code the C API uses, so digests
+//! printed in a shell always agree with those a program gets through `get_checksum`.
+//!
+//!     file-checksum [-a ALGORITHM]... [-r] [-j JOBS] [--tag] [--json] [FILE]...
+//!     file-checksum -c [--json] [-j JOBS] [SUMFILE]...
+//!
+//! With no FILE, or when FILE is `-`, standard input is read. The exit status is 0 if everything
+//! succeeded, 1 if a file couldn't be hashed or failed its check and 2 for a usage error.
+
+extern crate file_checksum;
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use file_checksum::batch;
+use file_checksum::checksum::{self, Algorithm, Hasher, CHUNK_SIZE};
+use file_checksum::error::Error;
+use file_checksum::manifest::{self, ManifestOptions};
+use file_checksum::sumfile::{self, SumEntry, SumFormat, VerifyStatus};
+
+const USAGE: &str = "Usage: file-checksum [OPTION]... [FILE]...
+Print or check checksums of files. With no FILE, or when FILE is -, read standard input.
+
+  -a, --algorithm NAME  algorithm to use, may be repeated or comma separated:
+                        CRC32, ADLER32, MD5, SHA1, SHA256 (default), SHA512, BLAKE2b
+  -r, --recursive       hash the files under any directories given
+      --follow-symlinks follow symbolic links while recursing
+  -c, --check           read checksums from the FILEs and check them
+  -j, --jobs N          hash up to N files at once, 0 for one per CPU (default)
+      --tag             print BSD style lines, implied by more than one algorithm
+      --json            print the results as a JSON array
+  -h, --help            print this help
+  -V, --version         print the version
+";
+
+#[derive(Debug, Default)]
+struct Options {
+    algorithms: Vec<Algorithm>,
+    recursive: bool,
+    follow_symlinks: bool,
+    check: bool,
+    jobs: usize,
+    tag: bool,
+    json: bool,
+    files: Vec<PathBuf>,
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("file-checksum: {}\nTry 'file-checksum --help' for more information.", message);
+    process::exit(2);
+}
+
+fn parse_args<I: Iterator<Item = OsString>>(mut args: I) -> Options {
+    let mut options = Options::default();
+    let mut only_files = false;
+    while let Some(arg) = args.next() {
+        // File names are kept as they are, they needn't be valid UTF-8
+        if only_files || arg == "-" || !arg.to_string_lossy().starts_with('-') {
+            options.files.push(PathBuf::from(arg));
+            continue;
+        }
+        let arg = match arg.to_str() {
+            Some(arg) => arg.to_string(),
+            None => usage_error(&format!("unrecognised option '{}'", arg.to_string_lossy())),
+        };
+        // Long options may carry their value after an '='
+        let (name, mut value) = match arg.find('=') {
+            Some(i) if arg.starts_with("--") => (arg[..i].to_string(), Some(arg[i + 1..].to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut take_value = || {
+            value
+                .take()
+                .or_else(|| args.next().map(|value| value.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| usage_error(&format!("option '{}' needs a value", name)))
+        };
+        match name.as_str() {
+            "-a" | "--algorithm" => {
+                for algorithm_name in take_value().split(',') {
+                    let algorithm = Algorithm::from_name(algorithm_name)
+                        .unwrap_or_else(|| usage_error(&format!("unsupported algorithm '{}'", algorithm_name)));
+                    if !options.algorithms.contains(&algorithm) {
+                        options.algorithms.push(algorithm);
+                    }
+                }
+            }
+            "-j" | "--jobs" => {
+                let jobs = take_value();
+                options.jobs = jobs.parse().unwrap_or_else(|_| usage_error(&format!("invalid number of jobs '{}'", jobs)));
+            }
+            "-r" | "--recursive" => options.recursive = true,
+            "--follow-symlinks" => options.follow_symlinks = true,
+            "-c" | "--check" => options.check = true,
+            "--tag" => options.tag = true,
+            "--json" => options.json = true,
+            "--" => only_files = true,
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            "-V" | "--version" => {
+                println!("file-checksum {}", env!("CARGO_PKG_VERSION"));
+                process::exit(0);
+            }
+            _ => usage_error(&format!("unrecognised option '{}'", arg)),
+        }
+        if value.is_some() {
+            usage_error(&format!("option '{}' doesn't take a value", name));
+        }
+    }
+    if options.algorithms.is_empty() {
+        options.algorithms.push(Algorithm::default());
+    }
+    if options.files.is_empty() {
+        options.files.push(PathBuf::from("-"));
+    }
+    options
+}
+
+fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Hashes everything `reader` yields with each of `algorithms` in a single pass.
+fn checksum_reader_all<R: Read>(reader: &mut R, algorithms: &[Algorithm]) -> io::Result<Vec<Vec<u8>>> {
+    let mut hashers: Vec<Hasher> = algorithms.iter().map(|a| Hasher::new(*a)).collect();
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let count = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        for hasher in hashers.iter_mut() {
+            hasher.update(&buffer[..count]);
+        }
+    }
+    Ok(hashers.into_iter().map(Hasher::finalize).collect())
+}
+
+fn checksum_path(path: &Path, algorithms: &[Algorithm]) -> Result<Vec<Vec<u8>>, Error> {
+    let to_error = |err| Error::from_io(&err, &path.to_string_lossy());
+    if is_stdin(path) {
+        return checksum_reader_all(&mut io::stdin().lock(), algorithms).map_err(to_error);
+    }
+    if algorithms.len() == 1 {
+        // Lets a large file go through a memory map
+        return checksum::checksum_file(path, algorithms[0]).map(|digest| vec![digest]).map_err(to_error);
+    }
+    checksum_reader_all(&mut fs::File::open(path).map_err(to_error)?, algorithms).map_err(to_error)
+}
+
+/// Expands directories into the files under them when recursing. A directory that can't be
+/// walked is kept along with its error so it's reported in order with everything else.
+fn expand_paths(options: &Options) -> Vec<(PathBuf, Option<Error>)> {
+    let manifest_options = ManifestOptions { follow_symlinks: options.follow_symlinks, ..ManifestOptions::default() };
+    let mut paths = Vec::new();
+    for path in options.files.iter() {
+        if options.recursive && !is_stdin(path) && path.is_dir() {
+            match manifest::find_files(path, &manifest_options) {
+                Ok(files) => paths.extend(files.into_iter().map(|file| (file, None))),
+                Err(err) => paths.push((path.clone(), Some(err))),
+            }
+        } else {
+            paths.push((path.clone(), None));
+        }
+    }
+    paths
+}
+
+fn path_bytes(path: &Path) -> Vec<u8> {
+    manifest::os_str_bytes(path.as_os_str())
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Prints the objects of a JSON array, one per line.
+fn print_json(out: &mut dyn Write, objects: &[String]) -> io::Result<()> {
+    writeln!(out, "[")?;
+    for (i, object) in objects.iter().enumerate() {
+        let separator = if i + 1 < objects.len() { "," } else { "" };
+        writeln!(out, "  {}{}", object, separator)?;
+    }
+    writeln!(out, "]")
+}
+
+fn run_hash(options: &Options, out: &mut dyn Write) -> io::Result<bool> {
+    let paths = expand_paths(options);
+    let results = batch::run_pool(&paths, options.jobs, |(path, err)| match err {
+        Some(err) => Err(err.clone()),
+        None => checksum_path(path, &options.algorithms),
+    });
+
+    let format = if options.tag || options.algorithms.len() > 1 { SumFormat::Bsd } else { SumFormat::Gnu };
+    let mut ok = true;
+    let mut objects = Vec::new();
+    for ((path, _), result) in paths.iter().zip(results) {
+        match result {
+            Ok(digests) => {
+                for (algorithm, digest) in options.algorithms.iter().zip(digests) {
+                    if options.json {
+                        objects.push(format!(
+                            "{{\"path\": {}, \"algorithm\": {}, \"digest\": {}}}",
+                            json_string(&path.to_string_lossy()),
+                            json_string(algorithm.name()),
+                            json_string(&checksum::to_hex(&digest))
+                        ));
+                    } else {
+                        let entry = SumEntry { path: path_bytes(path), algorithm: *algorithm, digest };
+                        let line = sumfile::format_sums(&[entry], format).map_err(|err| io::Error::other(err.message))?;
+                        out.write_all(&line)?;
+                    }
+                }
+            }
+            Err(err) => {
+                ok = false;
+                if options.json {
+                    objects.push(format!(
+                        "{{\"path\": {}, \"error\": {}}}",
+                        json_string(&path.to_string_lossy()),
+                        json_string(&err.message)
+                    ));
+                }
+                eprintln!("file-checksum: {}", err);
+            }
+        }
+    }
+    if options.json {
+        print_json(out, &objects)?;
+    }
+    Ok(ok)
+}
+
+fn status_name(status: VerifyStatus) -> &'static str {
+    match status {
+        VerifyStatus::Ok => "OK",
+        VerifyStatus::Failed => "FAILED",
+        VerifyStatus::Missing => "MISSING",
+        VerifyStatus::Unreadable => "UNREADABLE",
+    }
+}
+
+/// Reads and parses one checksum file, returning its entries and the directory they're relative to.
+fn read_sum_file(path: &Path, hint: Option<Algorithm>) -> Result<(Vec<SumEntry>, PathBuf), Error> {
+    let data = if is_stdin(path) {
+        let mut data = Vec::new();
+        io::stdin().lock().read_to_end(&mut data).map(|_| data)
+    } else {
+        fs::read(path)
+    };
+    let data = data.map_err(|err| Error::from_io(&err, &path.to_string_lossy()))?;
+    let hint = hint.or_else(|| path.file_name().and_then(|name| sumfile::algorithm_from_file_name(&name.to_string_lossy())));
+    let entries = sumfile::parse_sums(&data, None, hint)?;
+    let base_dir = if is_stdin(path) { PathBuf::new() } else { path.parent().map(Path::to_path_buf).unwrap_or_default() };
+    Ok((entries, base_dir))
+}
+
+fn run_check(options: &Options, out: &mut dyn Write) -> io::Result<bool> {
+    // An explicit algorithm only matters for GNU lines, whose algorithm can be ambiguous
+    let hint = if options.algorithms.len() == 1 { Some(options.algorithms[0]) } else { None };
+    let mut ok = true;
+    let mut objects = Vec::new();
+    for sum_file in options.files.iter() {
+        let (entries, base_dir) = match read_sum_file(sum_file, hint) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                ok = false;
+                eprintln!("file-checksum: {}", err);
+                continue;
+            }
+        };
+        for result in batch::run_pool(&entries, options.jobs, |entry| sumfile::verify_entry(entry, &base_dir)) {
+            ok &= result.status == VerifyStatus::Ok;
+            if options.json {
+                objects.push(format!(
+                    "{{\"path\": {}, \"status\": {}}}",
+                    json_string(&String::from_utf8_lossy(&result.path)),
+                    json_string(status_name(result.status))
+                ));
+            } else {
+                out.write_all(&result.path)?;
+                writeln!(out, ": {}", status_name(result.status))?;
+            }
+        }
+    }
+    if options.json {
+        print_json(out, &objects)?;
+    }
+    Ok(ok)
+}
+
+fn main() {
+    let options = parse_args(env::args_os().skip(1));
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let result = if options.check { run_check(&options, &mut out) } else { run_hash(&options, &mut out) };
+    match result.and_then(|ok| out.flush().map(|_| ok)) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(err) => {
+            eprintln!("file-checksum: {}", err);
+            process::exit(1);
+        }
+    }
+}
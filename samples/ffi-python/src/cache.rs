@@ -0,0 +1,561 @@
+//! An optional on-disk cache of digests. A file is only hashed again if its size, modification
+//! time or inode have changed since its digest was stored, so repeatedly checking an unchanged
+//! tree costs a `stat` per file rather than a read.
+//!
+//! The cache is process wide and off until `file_checksum_cache_open` is called. Once open, the
+//! path based calls such as `get_checksum`, the batch calls and checksum file verification all go
+//! through it. It is kept in memory and only written back by `file_checksum_cache_flush` or
+//! `file_checksum_cache_close`.
+//!
+//! Cache file layout, integers big-endian, entries least recently used first so the order of use
+//! survives a reload:
+//!
+//! ```text
+//! "FCC1" | entry*
+//! entry = path length u32 | path | algorithm u8 | size u64 | mtime seconds u64 |
+//!         mtime nanoseconds u32 | device u64 | inode u64 | digest length u8 | digest
+//! ```
+
+use std::collections::HashMap;
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{self, Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use libc::{c_char, c_int};
+
+use checksum::{self, Algorithm};
+use error::{self, Error, ErrorCode};
+use ffi::path_from_c;
+use logging::{self, LogLevel};
+use manifest;
+
+/// The number of entries kept when no cap is given.
+pub const DEFAULT_CACHE_ENTRIES: usize = 100_000;
+
+const MAGIC: &[u8; 4] = b"FCC1";
+
+/// The longest path cached, PATH_MAX on Linux. A longer length in a cache file means it's damaged.
+const MAX_PATH_LEN: usize = 4096;
+
+/// Files modified this recently aren't cached. A write in the same clock tick as the hash could
+/// otherwise change the file without changing its modification time.
+const MIN_AGE: Duration = Duration::from_secs(2);
+
+/// What identifies one version of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fingerprint {
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+    device: u64,
+    inode: u64,
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> (u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> (u64, u64) {
+    (0, 0)
+}
+
+impl Fingerprint {
+    /// Returns `None` for files that mustn't be cached: anything but a regular file, and files
+    /// modified within the last `MIN_AGE`.
+    fn of(metadata: &Metadata) -> Option<Fingerprint> {
+        if !metadata.is_file() {
+            return None;
+        }
+        let modified = metadata.modified().ok()?;
+        if SystemTime::now().duration_since(modified).ok().is_none_or(|age| age < MIN_AGE) {
+            return None;
+        }
+        // Files from before 1970 are too odd to be worth caching
+        let since_epoch = modified.duration_since(UNIX_EPOCH).ok()?;
+        let (device, inode) = file_id(metadata);
+        Some(Fingerprint { size: metadata.len(), mtime_secs: since_epoch.as_secs(), mtime_nanos: since_epoch.subsec_nanos(), device, inode })
+    }
+}
+
+/// The absolute path of a file, as bytes, and the algorithm of its digest.
+type CacheKey = (Vec<u8>, Algorithm);
+
+/// An entry ready to be stored once a file has been hashed.
+type NewEntry = (CacheKey, Fingerprint);
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    fingerprint: Fingerprint,
+    digest: Vec<u8>,
+    /// When the entry was last used, on the cache's own clock, for evicting the oldest entries.
+    last_used: u64,
+}
+
+/// Digests of files keyed by absolute path and algorithm.
+#[derive(Debug)]
+pub struct Cache {
+    path: Option<PathBuf>,
+    max_entries: usize,
+    entries: HashMap<CacheKey, CacheEntry>,
+    clock: u64,
+    dirty: bool,
+}
+
+/// The path part of a file's cache key, or `None` if it can't be cached.
+fn path_key(path: &Path) -> Option<Vec<u8>> {
+    let path = manifest::os_str_bytes(path::absolute(path).ok()?.as_os_str());
+    if path.len() > MAX_PATH_LEN {
+        return None;
+    }
+    Some(path)
+}
+
+fn cache_key(path: &Path, algorithm: Algorithm) -> Option<CacheKey> {
+    path_key(path).map(|path| (path, algorithm))
+}
+
+fn read_exact_array<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut value = [0; N];
+    reader.read_exact(&mut value)?;
+    Ok(value)
+}
+
+fn read_entry<R: Read>(reader: &mut R) -> io::Result<(CacheKey, CacheEntry)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid cache entry");
+    let path_len = u32::from_be_bytes(read_exact_array(reader)?) as usize;
+    if path_len > MAX_PATH_LEN {
+        return Err(invalid());
+    }
+    let mut path = vec![0; path_len];
+    reader.read_exact(&mut path)?;
+    let algorithm = Algorithm::from_raw(i32::from(read_exact_array::<_, 1>(reader)?[0])).ok_or_else(invalid)?;
+    let fingerprint = Fingerprint {
+        size: u64::from_be_bytes(read_exact_array(reader)?),
+        mtime_secs: u64::from_be_bytes(read_exact_array(reader)?),
+        mtime_nanos: u32::from_be_bytes(read_exact_array(reader)?),
+        device: u64::from_be_bytes(read_exact_array(reader)?),
+        inode: u64::from_be_bytes(read_exact_array(reader)?),
+    };
+    let mut digest = vec![0; read_exact_array::<_, 1>(reader)?[0] as usize];
+    reader.read_exact(&mut digest)?;
+    if digest.len() != algorithm.digest_len() {
+        return Err(invalid());
+    }
+    Ok(((path, algorithm), CacheEntry { fingerprint, digest, last_used: 0 }))
+}
+
+impl Cache {
+    /// A cache that is never saved.
+    pub fn in_memory(max_entries: usize) -> Cache {
+        Cache { path: None, max_entries: max_entries.max(1), entries: HashMap::new(), clock: 0, dirty: false }
+    }
+
+    /// Loads the cache saved at `path`, or starts an empty one if there's no file yet. A damaged
+    /// cache file is discarded rather than reported, since everything in it can be recomputed.
+    pub fn open<P: AsRef<Path>>(path: P, max_entries: usize) -> Result<Cache, Error> {
+        let path = path.as_ref();
+        let mut cache = Cache::in_memory(max_entries);
+        cache.path = Some(path.to_path_buf());
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(cache),
+            Err(err) => return Err(Error::from_io(&err, &path.to_string_lossy())),
+        };
+        let mut reader = BufReader::new(file);
+        if read_exact_array::<_, 4>(&mut reader).ok().as_ref() != Some(MAGIC) {
+            logging::log(LogLevel::Warn, "cache", format_args!("ignoring {}, it isn't a cache file", path.display()));
+            return Ok(cache);
+        }
+        loop {
+            // Stop cleanly at the end, a read error is caught by read_entry
+            if reader.fill_buf().ok().is_none_or(|buf| buf.is_empty()) {
+                break;
+            }
+            match read_entry(&mut reader) {
+                Ok((key, mut entry)) => {
+                    cache.clock += 1;
+                    entry.last_used = cache.clock;
+                    cache.entries.insert(key, entry);
+                }
+                Err(err) => {
+                    logging::log(LogLevel::Warn, "cache", format_args!("discarding damaged cache file {}: {}", path.display(), err));
+                    cache.entries.clear();
+                    break;
+                }
+            }
+        }
+        cache.evict();
+        Ok(cache)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Looks up the digest of a file in the state `metadata` describes.
+    fn get(&mut self, key: &CacheKey, metadata: &Metadata) -> Option<Vec<u8>> {
+        let fingerprint = Fingerprint::of(metadata)?;
+        self.clock += 1;
+        let clock = self.clock;
+        let entry = self.entries.get_mut(key).filter(|entry| entry.fingerprint == fingerprint)?;
+        entry.last_used = clock;
+        Some(entry.digest.clone())
+    }
+
+    fn insert(&mut self, key: CacheKey, fingerprint: Fingerprint, digest: Vec<u8>) {
+        self.clock += 1;
+        self.entries.insert(key, CacheEntry { fingerprint, digest, last_used: self.clock });
+        self.dirty = true;
+        self.evict();
+    }
+
+    /// Drops the least recently used entries once there are more than allowed, down to 90% of
+    /// the cap so eviction doesn't happen on every insert.
+    fn evict(&mut self) {
+        if self.entries.len() <= self.max_entries {
+            return;
+        }
+        let keep = (self.max_entries - self.max_entries / 10).max(1);
+        let mut ages: Vec<u64> = self.entries.values().map(|entry| entry.last_used).collect();
+        ages.sort_unstable();
+        let mut to_remove = self.entries.len() - keep;
+        let cutoff = ages[to_remove - 1];
+        self.entries.retain(|_, entry| {
+            if to_remove > 0 && entry.last_used <= cutoff {
+                to_remove -= 1;
+                false
+            } else {
+                true
+            }
+        });
+        self.dirty = true;
+    }
+
+    /// Forgets whatever is stored for the file at `path`, for every algorithm.
+    pub fn invalidate<P: AsRef<Path>>(&mut self, path: P) {
+        if let Some(path) = path_key(path.as_ref()) {
+            let before = self.entries.len();
+            self.entries.retain(|key, _| key.0 != path);
+            self.dirty |= self.entries.len() != before;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.dirty |= !self.entries.is_empty();
+        self.entries.clear();
+    }
+
+    /// Returns the digest of the file at `path`, hashing it only if it isn't cached or `force` is
+    /// set.
+    pub fn checksum_file<P: AsRef<Path>>(&mut self, path: P, algorithm: Algorithm, force: bool) -> io::Result<Vec<u8>> {
+        let mut pending = Pending::open(path.as_ref(), algorithm)?;
+        if !force {
+            if let Some(digest) = pending.key.as_ref().and_then(|key| self.get(key, &pending.metadata)) {
+                return Ok(digest);
+            }
+        }
+        let (digest, to_store) = pending.hash(algorithm)?;
+        if let Some((key, fingerprint)) = to_store {
+            self.insert(key, fingerprint, digest.clone());
+        }
+        Ok(digest)
+    }
+
+    /// Writes the cache back to its file if anything changed, replacing the file atomically.
+    pub fn save(&mut self) -> Result<(), Error> {
+        let path = match self.path {
+            Some(ref path) if self.dirty => path.clone(),
+            _ => return Ok(()),
+        };
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let to_error = |err: io::Error| Error::from_io(&err, &path.to_string_lossy());
+        let mut out = BufWriter::new(File::create(&temp).map_err(to_error)?);
+        out.write_all(MAGIC).map_err(to_error)?;
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort_by_key(|(_, entry)| entry.last_used);
+        for (&(ref file, algorithm), entry) in entries {
+            let fingerprint = &entry.fingerprint;
+            out.write_all(&(file.len() as u32).to_be_bytes()).map_err(to_error)?;
+            out.write_all(file).map_err(to_error)?;
+            out.write_all(&[algorithm as u8]).map_err(to_error)?;
+            out.write_all(&fingerprint.size.to_be_bytes()).map_err(to_error)?;
+            out.write_all(&fingerprint.mtime_secs.to_be_bytes()).map_err(to_error)?;
+            out.write_all(&fingerprint.mtime_nanos.to_be_bytes()).map_err(to_error)?;
+            out.write_all(&fingerprint.device.to_be_bytes()).map_err(to_error)?;
+            out.write_all(&fingerprint.inode.to_be_bytes()).map_err(to_error)?;
+            out.write_all(&[entry.digest.len() as u8]).map_err(to_error)?;
+            out.write_all(&entry.digest).map_err(to_error)?;
+        }
+        out.into_inner().map_err(|err| to_error(err.into_error()))?.sync_all().map_err(to_error)?;
+        fs::rename(&temp, &path).map_err(to_error)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// A file opened for a cached checksum, split from the hashing so a shared cache needn't be
+/// locked while the file is read.
+struct Pending {
+    file: File,
+    metadata: Metadata,
+    key: Option<CacheKey>,
+}
+
+impl Pending {
+    fn open(path: &Path, algorithm: Algorithm) -> io::Result<Pending> {
+        logging::log(LogLevel::Debug, "cache", format_args!("opening {} for {}", path.display(), algorithm.name()));
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        Ok(Pending { file, metadata, key: cache_key(path, algorithm) })
+    }
+
+    /// Hashes the file, also returning the entry to store if the file can be cached and didn't
+    /// change while it was read.
+    fn hash(&mut self, algorithm: Algorithm) -> io::Result<(Vec<u8>, Option<NewEntry>)> {
+        let digest = checksum::checksum_open_file(&mut self.file, algorithm, checksum::mmap_threshold(), |_, _| true)?.expect("never cancelled");
+        let before = Fingerprint::of(&self.metadata);
+        let after = self.file.metadata().ok().as_ref().and_then(Fingerprint::of);
+        let to_store = match (self.key.take(), before) {
+            (Some(key), Some(before)) if after == Some(before) => Some((key, before)),
+            _ => None,
+        };
+        Ok((digest, to_store))
+    }
+}
+
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+
+fn global() -> MutexGuard<'static, Option<Cache>> {
+    // Entries are only ever inserted whole so a poisoned lock is still usable
+    CACHE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Returns the digest of the file at `path` through the process wide cache if one is open, and
+/// by hashing the file otherwise. The lock isn't held while hashing so other threads can use the
+/// cache meanwhile.
+pub fn checksum_file<P: AsRef<Path>>(path: P, algorithm: Algorithm, force: bool) -> io::Result<Vec<u8>> {
+    if global().is_none() {
+        return checksum::checksum_file(path, algorithm);
+    }
+    let mut pending = Pending::open(path.as_ref(), algorithm)?;
+    if !force {
+        let cached = pending.key.as_ref().and_then(|key| global().as_mut()?.get(key, &pending.metadata));
+        if let Some(digest) = cached {
+            logging::log(LogLevel::Debug, "cache", format_args!("hit for {}", path.as_ref().display()));
+            return Ok(digest);
+        }
+    }
+    let (digest, to_store) = pending.hash(algorithm)?;
+    // The lock is released before logging, since the log callback may call back into the cache
+    let stored = match (to_store, global().as_mut()) {
+        (Some((key, fingerprint)), Some(cache)) => {
+            cache.insert(key, fingerprint, digest.clone());
+            true
+        }
+        _ => false,
+    };
+    if stored {
+        logging::log(LogLevel::Debug, "cache", format_args!("miss for {}, digest stored", path.as_ref().display()));
+    } else {
+        logging::log(LogLevel::Debug, "cache", format_args!("miss for {}, too new or changed while read to store", path.as_ref().display()));
+    }
+    Ok(digest)
+}
+
+/// Opens the cache saved at `path`, replacing any cache already open, which is saved first. At
+/// most `max_entries` digests are kept, the least recently used being dropped first; 0 means
+/// `DEFAULT_CACHE_ENTRIES`.
+pub fn open_global<P: AsRef<Path>>(path: P, max_entries: usize) -> Result<(), Error> {
+    let max_entries = if max_entries == 0 { DEFAULT_CACHE_ENTRIES } else { max_entries };
+    let cache = Cache::open(path.as_ref(), max_entries)?;
+    logging::log(LogLevel::Info, "cache", format_args!("opened {} with {} entries", path.as_ref().display(), cache.len()));
+    let mut global = global();
+    let result = global.as_mut().map_or(Ok(()), Cache::save);
+    *global = Some(cache);
+    result
+}
+
+/// Saves and closes the process wide cache. It is closed even if saving fails.
+pub fn close_global() -> Result<(), Error> {
+    global().take().map_or(Ok(()), |mut cache| cache.save())
+}
+
+fn with_global<F: FnOnce(&mut Cache) -> Result<(), Error>>(f: F) -> Result<(), Error> {
+    match global().as_mut() {
+        Some(cache) => f(cache),
+        None => Err(Error::new(ErrorCode::InvalidArgument, "no cache is open")),
+    }
+}
+
+/// Opens the digest cache saved at `path`, creating it on the next flush if it doesn't exist,
+/// and routes the path based calls through it. At most `max_entries` digests are kept, or
+/// `FILE_CHECKSUM_DEFAULT_CACHE_ENTRIES` if it is 0. A cache that is already open is saved and
+/// replaced. Returns 0 or an `ErrorCode`.
+///
+/// # Safety
+///
+/// `path` must be NULL or point to a valid NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn file_checksum_cache_open(path: *const c_char, max_entries: usize) -> c_int {
+    error::guard_status(|| open_global(path_from_c(path)?, max_entries)) as c_int
+}
+
+/// Writes the open cache back to its file if it changed. Returns 0 or an `ErrorCode`.
+#[no_mangle]
+pub extern "C" fn file_checksum_cache_flush() -> c_int {
+    error::guard_status(|| with_global(Cache::save)) as c_int
+}
+
+/// Saves and closes the open cache, after which files are always hashed. Does nothing if no cache
+/// is open. Returns 0 or an `ErrorCode`.
+#[no_mangle]
+pub extern "C" fn file_checksum_cache_close() -> c_int {
+    error::guard_status(close_global) as c_int
+}
+
+/// Forgets the cached digests of the file at `filepath`. Returns 0 or an `ErrorCode`.
+///
+/// # Safety
+///
+/// `filepath` must be NULL or point to a valid NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn file_checksum_cache_invalidate(filepath: *const c_char) -> c_int {
+    error::guard_status(|| {
+        let filepath = path_from_c(filepath)?;
+        with_global(|cache| {
+            cache.invalidate(filepath);
+            Ok(())
+        })
+    }) as c_int
+}
+
+/// Forgets every cached digest. Returns 0 or an `ErrorCode`.
+#[no_mangle]
+pub extern "C" fn file_checksum_cache_clear() -> c_int {
+    error::guard_status(|| {
+        with_global(|cache| {
+            cache.clear();
+            Ok(())
+        })
+    }) as c_int
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+
+    /// Writes a file whose modification time is far enough in the past for it to be cached.
+    fn write_old(path: &Path, data: &[u8]) {
+        fs::write(path, data).unwrap();
+        let file = fs::OpenOptions::new().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(3600)).unwrap();
+    }
+
+    #[test]
+    fn hits_and_misses() {
+        let path = temp_dir().join("file_checksum_cache_hits.txt");
+        write_old(&path, b"abc");
+        let mut cache = Cache::in_memory(10);
+        let digest = cache.checksum_file(&path, Algorithm::Md5, false).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.checksum_file(&path, Algorithm::Md5, false).unwrap(), digest);
+
+        // Same size and mtime but different content: only a forced rehash notices
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        fs::write(&path, b"xyz").unwrap();
+        fs::OpenOptions::new().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+        assert_eq!(cache.checksum_file(&path, Algorithm::Md5, false).unwrap(), digest);
+        let forced = cache.checksum_file(&path, Algorithm::Md5, true).unwrap();
+        assert_eq!(forced, checksum::checksum_bytes(b"xyz", Algorithm::Md5));
+        assert_eq!(cache.checksum_file(&path, Algorithm::Md5, false).unwrap(), forced);
+
+        // A changed mtime is a miss
+        write_old(&path, b"abcd");
+        assert_eq!(cache.checksum_file(&path, Algorithm::Md5, false).unwrap(), checksum::checksum_bytes(b"abcd", Algorithm::Md5));
+
+        cache.invalidate(&path);
+        assert!(cache.is_empty());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn recent_files_are_not_cached() {
+        let path = temp_dir().join("file_checksum_cache_recent.txt");
+        fs::write(&path, b"abc").unwrap();
+        let mut cache = Cache::in_memory(10);
+        cache.checksum_file(&path, Algorithm::Md5, false).unwrap();
+        assert!(cache.is_empty());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn eviction() {
+        let dir = temp_dir().join("file_checksum_cache_eviction");
+        fs::create_dir_all(&dir).unwrap();
+        let mut cache = Cache::in_memory(10);
+        for i in 0..11 {
+            let path = dir.join(format!("{}.txt", i));
+            write_old(&path, format!("{}", i).as_bytes());
+            cache.checksum_file(&path, Algorithm::Crc32, false).unwrap();
+            if i == 9 {
+                // Make the first file the most recently used
+                cache.checksum_file(dir.join("0.txt"), Algorithm::Crc32, false).unwrap();
+            }
+        }
+        assert_eq!(cache.len(), 9);
+        let key = cache_key(&dir.join("0.txt"), Algorithm::Crc32).unwrap();
+        assert!(cache.entries.contains_key(&key));
+        let key = cache_key(&dir.join("1.txt"), Algorithm::Crc32).unwrap();
+        assert!(!cache.entries.contains_key(&key));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = temp_dir().join("file_checksum_cache_save");
+        fs::create_dir_all(&dir).unwrap();
+        let cache_path = dir.join("cache.bin");
+        let _ = fs::remove_file(&cache_path);
+        let file = dir.join("data.txt");
+        write_old(&file, b"abc");
+
+        let mut cache = Cache::open(&cache_path, 10).unwrap();
+        cache.checksum_file(&file, Algorithm::Sha256, false).unwrap();
+        cache.checksum_file(&file, Algorithm::Blake2b, false).unwrap();
+        cache.save().unwrap();
+        let loaded = Cache::open(&cache_path, 10).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(Cache::open(&cache_path, 1).unwrap().len(), 1);
+
+        // Entries keep their order of use across a reload, so the least recently used go first
+        let other = dir.join("other.txt");
+        write_old(&other, b"other");
+        let mut cache = Cache::open(&cache_path, 10).unwrap();
+        cache.checksum_file(&other, Algorithm::Sha256, false).unwrap();
+        cache.checksum_file(&file, Algorithm::Sha256, false).unwrap();
+        cache.save().unwrap();
+        let loaded = Cache::open(&cache_path, 2).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert!(!loaded.entries.contains_key(&cache_key(&file, Algorithm::Blake2b).unwrap()));
+
+        // A damaged cache is simply empty
+        let data = fs::read(&cache_path).unwrap();
+        fs::write(&cache_path, &data[..data.len() - 3]).unwrap();
+        assert!(Cache::open(&cache_path, 10).unwrap().is_empty());
+        fs::write(&cache_path, b"nonsense").unwrap();
+        assert!(Cache::open(&cache_path, 10).unwrap().is_empty());
+        // A huge path length is rejected before anything is allocated for it
+        fs::write(&cache_path, [&MAGIC[..], &u32::MAX.to_be_bytes()].concat()).unwrap();
+        assert!(Cache::open(&cache_path, 10).unwrap().is_empty());
+    }
+}
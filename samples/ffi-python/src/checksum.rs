@@ -0,0 +1,471 @@
+//! The hashing core behind the exported functions. Files are streamed through the digest in
+//! fixed-size chunks so memory use stays flat no matter how big the file is.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use adler2::Adler32;
+use blake2::Blake2b512;
+use md5::Md5;
+use memmap2::Mmap;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+
+use logging::{self, LogLevel};
+
+/// Number of bytes read from the file for each update of the digest.
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Length in bytes of the longest digest of any supported algorithm.
+pub const MAX_DIGEST_LEN: usize = 64;
+
+/// Length of the longest hex digest of any supported algorithm.
+pub const MAX_HEX_DIGEST_LEN: usize = 128;
+
+/// Size of a buffer that holds any hex digest and its NUL terminator.
+pub const HEX_DIGEST_BUFFER_LEN: usize = MAX_HEX_DIGEST_LEN + 1;
+
+/// The checksum and digest algorithms the library supports. The discriminants are part of the C
+/// ABI and must not change.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Algorithm {
+    Crc32 = 0,
+    Adler32 = 1,
+    Md5 = 2,
+    Sha1 = 3,
+    #[default]
+    Sha256 = 4,
+    Sha512 = 5,
+    Blake2b = 6,
+}
+
+impl Algorithm {
+    /// Every supported algorithm, in discriminant order.
+    ///
+    /// cbindgen:ignore
+    pub const ALL: [Algorithm; 7] = [
+        Algorithm::Crc32,
+        Algorithm::Adler32,
+        Algorithm::Md5,
+        Algorithm::Sha1,
+        Algorithm::Sha256,
+        Algorithm::Sha512,
+        Algorithm::Blake2b,
+    ];
+
+    /// Converts a raw value received over the C ABI, returning `None` if it isn't a known
+    /// algorithm.
+    pub fn from_raw(value: i32) -> Option<Algorithm> {
+        Algorithm::ALL.iter().cloned().find(|a| *a as i32 == value)
+    }
+
+    /// Looks up an algorithm by its name, ignoring case and dashes so "sha-256" finds SHA256.
+    pub fn from_name(name: &str) -> Option<Algorithm> {
+        let name: String = name.chars().filter(|c| *c != '-').collect();
+        Algorithm::ALL.iter().cloned().find(|a| a.name().eq_ignore_ascii_case(&name))
+    }
+
+    /// The conventional name of the algorithm, as used in BSD style checksum listings.
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Crc32 => "CRC32",
+            Algorithm::Adler32 => "ADLER32",
+            Algorithm::Md5 => "MD5",
+            Algorithm::Sha1 => "SHA1",
+            Algorithm::Sha256 => "SHA256",
+            Algorithm::Sha512 => "SHA512",
+            Algorithm::Blake2b => "BLAKE2b",
+        }
+    }
+
+    /// Length of the digest in bytes.
+    pub fn digest_len(self) -> usize {
+        match self {
+            Algorithm::Crc32 | Algorithm::Adler32 => 4,
+            Algorithm::Md5 => 16,
+            Algorithm::Sha1 => 20,
+            Algorithm::Sha256 => 32,
+            Algorithm::Sha512 | Algorithm::Blake2b => 64,
+        }
+    }
+}
+
+/// Running state of a digest for one of the supported algorithms.
+#[derive(Clone)]
+pub enum Hasher {
+    Crc32(crc32fast::Hasher),
+    Adler32(Adler32),
+    Md5(Md5),
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Blake2b(Blake2b512),
+}
+
+impl Hasher {
+    pub fn new(algorithm: Algorithm) -> Hasher {
+        match algorithm {
+            Algorithm::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+            Algorithm::Adler32 => Hasher::Adler32(Adler32::new()),
+            Algorithm::Md5 => Hasher::Md5(Md5::new()),
+            Algorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            Algorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            Algorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+            Algorithm::Blake2b => Hasher::Blake2b(Blake2b512::new()),
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        match *self {
+            Hasher::Crc32(_) => Algorithm::Crc32,
+            Hasher::Adler32(_) => Algorithm::Adler32,
+            Hasher::Md5(_) => Algorithm::Md5,
+            Hasher::Sha1(_) => Algorithm::Sha1,
+            Hasher::Sha256(_) => Algorithm::Sha256,
+            Hasher::Sha512(_) => Algorithm::Sha512,
+            Hasher::Blake2b(_) => Algorithm::Blake2b,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match *self {
+            Hasher::Crc32(ref mut h) => h.update(data),
+            Hasher::Adler32(ref mut h) => h.write_slice(data),
+            Hasher::Md5(ref mut h) => h.update(data),
+            Hasher::Sha1(ref mut h) => h.update(data),
+            Hasher::Sha256(ref mut h) => h.update(data),
+            Hasher::Sha512(ref mut h) => h.update(data),
+            Hasher::Blake2b(ref mut h) => h.update(data),
+        }
+    }
+
+    /// Consumes the hasher and returns the digest. The 32-bit checksums are returned big-endian
+    /// so their hex form matches what tools such as `crc32` and zlib print.
+    pub fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Crc32(h) => h.finalize().to_be_bytes().to_vec(),
+            Hasher::Adler32(h) => h.checksum().to_be_bytes().to_vec(),
+            Hasher::Md5(h) => h.finalize().to_vec(),
+            Hasher::Sha1(h) => h.finalize().to_vec(),
+            Hasher::Sha256(h) => h.finalize().to_vec(),
+            Hasher::Sha512(h) => h.finalize().to_vec(),
+            Hasher::Blake2b(h) => h.finalize().to_vec(),
+        }
+    }
+}
+
+/// Reads everything from `reader` and returns its digest.
+pub fn checksum_reader<R: Read>(reader: &mut R, algorithm: Algorithm) -> io::Result<Vec<u8>> {
+    checksum_reader_with_progress(reader, algorithm, |_| true).map(|digest| digest.expect("never cancelled"))
+}
+
+/// Like `checksum_reader` but calls `progress` with the number of bytes hashed so far, once before
+/// the first read and then after every chunk. If `progress` returns false hashing stops and
+/// `None` is returned.
+pub fn checksum_reader_with_progress<R, F>(reader: &mut R, algorithm: Algorithm, progress: F) -> io::Result<Option<Vec<u8>>>
+where
+    R: Read,
+    F: FnMut(u64) -> bool,
+{
+    checksum_reader_with_buffer_size(reader, algorithm, CHUNK_SIZE, progress)
+}
+
+/// Like `checksum_reader_with_progress` reading `buffer_size` bytes at a time.
+pub fn checksum_reader_with_buffer_size<R, F>(reader: &mut R, algorithm: Algorithm, buffer_size: usize, mut progress: F) -> io::Result<Option<Vec<u8>>>
+where
+    R: Read,
+    F: FnMut(u64) -> bool,
+{
+    let mut hasher = Hasher::new(algorithm);
+    let mut buffer = vec![0u8; buffer_size.max(1)];
+    let mut processed = 0u64;
+    if !progress(processed) {
+        return Ok(None);
+    }
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        hasher.update(&buffer[..read]);
+        processed += read as u64;
+        if !progress(processed) {
+            return Ok(None);
+        }
+    }
+    Ok(Some(hasher.finalize()))
+}
+
+/// Returns the digest of bytes already in memory.
+pub fn checksum_bytes(data: &[u8], algorithm: Algorithm) -> Vec<u8> {
+    checksum_bytes_with_progress(data, algorithm, |_| true).expect("never cancelled")
+}
+
+/// Like `checksum_bytes` but reports progress a chunk at a time as `checksum_reader_with_progress`
+/// does.
+pub fn checksum_bytes_with_progress<F: FnMut(u64) -> bool>(data: &[u8], algorithm: Algorithm, mut progress: F) -> Option<Vec<u8>> {
+    let mut hasher = Hasher::new(algorithm);
+    let mut processed = 0u64;
+    if !progress(processed) {
+        return None;
+    }
+    for chunk in data.chunks(CHUNK_SIZE) {
+        hasher.update(chunk);
+        processed += chunk.len() as u64;
+        if !progress(processed) {
+            return None;
+        }
+    }
+    Some(hasher.finalize())
+}
+
+/// Files at least this big are hashed through a memory map by default.
+pub const DEFAULT_MMAP_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+static MMAP_THRESHOLD: AtomicU64 = AtomicU64::new(DEFAULT_MMAP_THRESHOLD);
+
+/// The size from which regular files are hashed through a memory map rather than read, or 0 if
+/// memory maps are not used.
+pub fn mmap_threshold() -> u64 {
+    MMAP_THRESHOLD.load(Ordering::Relaxed)
+}
+
+/// Changes `mmap_threshold` for the whole process.
+pub fn set_mmap_threshold(threshold: u64) {
+    MMAP_THRESHOLD.store(threshold, Ordering::Relaxed);
+}
+
+/// Maps `file` into memory read-only, with a hint that it will be read sequentially.
+///
+/// The map is only valid while nobody truncates the file, and a file that shrinks underneath it
+/// can raise SIGBUS, as with any memory mapped read.
+pub fn map_file(file: &File) -> io::Result<Mmap> {
+    let map = unsafe { Mmap::map(file)? };
+    #[cfg(unix)]
+    {
+        // Only a hint, hashing works the same without it
+        let _ = map.advise(memmap2::Advice::Sequential);
+    }
+    Ok(map)
+}
+
+/// Hashes an open file from its current position to the end, through a memory map if what is
+/// left of a regular file is at least `threshold` bytes and with buffered reads otherwise. Pipes,
+/// devices and files that can't be mapped always take the buffered path. Either way the file is
+/// left positioned at the end.
+pub fn checksum_open_file<F>(file: &mut File, algorithm: Algorithm, threshold: u64, mut progress: F) -> io::Result<Option<Vec<u8>>>
+where
+    F: FnMut(u64, u64) -> bool,
+{
+    let metadata = file.metadata()?;
+    if !metadata.is_file() {
+        return checksum_reader_with_progress(file, algorithm, |processed| progress(processed, 0));
+    }
+
+    let position = file.stream_position()?;
+    let total = metadata.len().saturating_sub(position);
+    if threshold > 0 && total >= threshold {
+        match map_file(file) {
+            Ok(map) => {
+                logging::log(LogLevel::Trace, "checksum", format_args!("hashing {} bytes through a memory map", total));
+                let digest = checksum_bytes_with_progress(&map[position as usize..], algorithm, |processed| progress(processed, total));
+                file.seek(SeekFrom::End(0))?;
+                return Ok(digest);
+            }
+            Err(err) => logging::log(LogLevel::Warn, "checksum", format_args!("memory map failed, falling back to reads: {}", err)),
+        }
+    }
+    checksum_reader_with_progress(file, algorithm, |processed| progress(processed, total))
+}
+
+/// Opens the file at `path` and returns its digest.
+pub fn checksum_file<P: AsRef<Path>>(path: P, algorithm: Algorithm) -> io::Result<Vec<u8>> {
+    checksum_file_with_progress(path, algorithm, |_, _| true).map(|digest| digest.expect("never cancelled"))
+}
+
+/// Like `checksum_file` but reports progress as `checksum_reader_with_progress` does, also
+/// passing the size of the file, or 0 if it isn't known, e.g. for a pipe.
+pub fn checksum_file_with_progress<P, F>(path: P, algorithm: Algorithm, progress: F) -> io::Result<Option<Vec<u8>>>
+where
+    P: AsRef<Path>,
+    F: FnMut(u64, u64) -> bool,
+{
+    let path = path.as_ref();
+    logging::log(LogLevel::Debug, "checksum", format_args!("opening {} for {}", path.display(), algorithm.name()));
+    let mut file = File::open(path)?;
+    checksum_open_file(&mut file, algorithm, mmap_threshold(), progress)
+}
+
+/// Formats a digest as a lowercase hex string.
+pub fn to_hex(digest: &[u8]) -> String {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    let mut hex = String::with_capacity(digest.len() * 2);
+    for byte in digest {
+        hex.push(HEX[(byte >> 4) as usize] as char);
+        hex.push(HEX[(byte & 0xf) as usize] as char);
+    }
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn hex_of(data: &[u8], algorithm: Algorithm) -> String {
+        to_hex(&checksum_reader(&mut Cursor::new(data.to_vec()), algorithm).unwrap())
+    }
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(
+            hex_of(b"", Algorithm::Sha256),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn known_digests() {
+        let check = b"123456789";
+        assert_eq!(hex_of(check, Algorithm::Crc32), "cbf43926");
+        assert_eq!(hex_of(check, Algorithm::Adler32), "091e01de");
+
+        let abc = b"abc";
+        assert_eq!(hex_of(abc, Algorithm::Md5), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(hex_of(abc, Algorithm::Sha1), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(
+            hex_of(abc, Algorithm::Sha256),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex_of(abc, Algorithm::Sha512),
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+             2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+        );
+        assert_eq!(
+            hex_of(abc, Algorithm::Blake2b),
+            "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+             7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"
+        );
+    }
+
+    #[test]
+    fn digest_lengths() {
+        for algorithm in Algorithm::ALL.iter() {
+            assert_eq!(hex_of(b"x", *algorithm).len(), algorithm.digest_len() * 2);
+        }
+    }
+
+    #[test]
+    fn max_hex_digest_len() {
+        let longest = Algorithm::ALL.iter().map(|a| a.digest_len()).max().unwrap();
+        assert_eq!(MAX_HEX_DIGEST_LEN, longest * 2);
+    }
+
+    #[test]
+    fn from_raw() {
+        for algorithm in Algorithm::ALL.iter() {
+            assert_eq!(Algorithm::from_raw(*algorithm as i32), Some(*algorithm));
+        }
+        assert_eq!(Algorithm::from_raw(-1), None);
+        assert_eq!(Algorithm::from_raw(7), None);
+    }
+
+    #[test]
+    fn from_name() {
+        for algorithm in Algorithm::ALL.iter() {
+            assert_eq!(Algorithm::from_name(algorithm.name()), Some(*algorithm));
+        }
+        assert_eq!(Algorithm::from_name("sha-256"), Some(Algorithm::Sha256));
+        assert_eq!(Algorithm::from_name("blake2b"), Some(Algorithm::Blake2b));
+        assert_eq!(Algorithm::from_name("sha3"), None);
+    }
+
+    #[test]
+    fn input_spanning_several_chunks() {
+        // Feeding the data in one go must agree with the chunked read
+        let data: Vec<u8> = (0..CHUNK_SIZE * 3 + 17).map(|i| (i % 251) as u8).collect();
+        let expected = Sha256::digest(&data).to_vec();
+        assert_eq!(checksum_reader(&mut Cursor::new(data), Algorithm::Sha256).unwrap(), expected);
+    }
+
+    #[test]
+    fn progress_and_cancel() {
+        let data = vec![7u8; CHUNK_SIZE * 2 + 5];
+        let mut seen = Vec::new();
+        let digest = checksum_reader_with_progress(&mut Cursor::new(data.clone()), Algorithm::Sha1, |n| {
+            seen.push(n);
+            true
+        });
+        assert_eq!(digest.unwrap().unwrap(), checksum_reader(&mut Cursor::new(data.clone()), Algorithm::Sha1).unwrap());
+        assert_eq!(seen, vec![0, CHUNK_SIZE as u64, CHUNK_SIZE as u64 * 2, data.len() as u64]);
+
+        let mut calls = 0;
+        let cancelled = checksum_reader_with_progress(&mut Cursor::new(data), Algorithm::Sha1, |_| {
+            calls += 1;
+            calls < 2
+        });
+        assert_eq!(cancelled.unwrap(), None);
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn mapped_and_read_files_agree() {
+        let path = ::std::env::temp_dir().join("file_checksum_mmap.bin");
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 3).map(|i| (i % 13) as u8).collect();
+        ::std::fs::write(&path, &data).unwrap();
+        let expected = checksum_bytes(&data, Algorithm::Blake2b);
+        for threshold in [0, 1, data.len() as u64, data.len() as u64 + 1].iter() {
+            let mut file = File::open(&path).unwrap();
+            let mut calls = 0;
+            let digest = checksum_open_file(&mut file, Algorithm::Blake2b, *threshold, |_, total| {
+                calls += 1;
+                total == data.len() as u64
+            });
+            assert_eq!(digest.unwrap().unwrap(), expected);
+            assert_eq!(calls, 4);
+        }
+        ::std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn open_file_from_position() {
+        let path = ::std::env::temp_dir().join("file_checksum_position.bin");
+        let data: Vec<u8> = (0..CHUNK_SIZE + 100).map(|i| (i % 7) as u8).collect();
+        ::std::fs::write(&path, &data).unwrap();
+        let expected = checksum_bytes(&data[10..], Algorithm::Md5);
+        for threshold in [0, 1].iter() {
+            let mut file = File::open(&path).unwrap();
+            file.seek(SeekFrom::Start(10)).unwrap();
+            let digest = checksum_open_file(&mut file, Algorithm::Md5, *threshold, |_, total| total == data.len() as u64 - 10);
+            assert_eq!(digest.unwrap().unwrap(), expected);
+            assert_eq!(file.stream_position().unwrap(), data.len() as u64);
+        }
+        ::std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn special_files_are_read() {
+        // /dev/null can't be mapped and has no size, it must still hash as empty input
+        let mut file = File::open("/dev/null").unwrap();
+        let digest = checksum_open_file(&mut file, Algorithm::Sha256, 1, |_, total| total == 0);
+        assert_eq!(digest.unwrap().unwrap(), checksum_bytes(b"", Algorithm::Sha256));
+    }
+
+    #[test]
+    fn hex_is_lowercase() {
+        assert_eq!(to_hex(&[0x00, 0xab, 0xff, 0x10]), "00abff10");
+    }
+
+    #[test]
+    fn missing_file() {
+        let err = checksum_file("/this/path/does/not/exist", Algorithm::Sha256).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,502 @@
+//! Content-defined chunking in the manner of FastCDC. Chunk boundaries are placed where a rolling
+//! gear hash of the data matches a mask, so they follow the content rather than fixed offsets and
+//! an insertion only disturbs the chunks around it. Hashing every chunk and comparing digests
+//! across files shows how much of a corpus is duplicated.
+
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::ptr;
+use std::slice;
+use libc::{c_char, c_int};
+
+use batch;
+use checksum::{self, Algorithm, Hasher, CHUNK_SIZE};
+use error::{self, Error, ErrorCode};
+use ffi::{algorithm_from_c, bytes_into_c_string, from_c_array, into_c_array, path_from_c};
+
+/// splitmix64, used to fill the gear table at compile time.
+const fn splitmix64(state: u64) -> u64 {
+    let mut z = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        table[i] = splitmix64(i as u64);
+        i += 1;
+    }
+    table
+}
+
+/// A random value for each byte. Changing it moves every chunk boundary.
+static GEAR: [u64; 256] = gear_table();
+
+/// Chunk size limits and the digest used to identify chunks. The defaults are 2 KiB, 8 KiB and
+/// 64 KiB with SHA-256.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkerOptions {
+    pub min_size: usize,
+    /// The size chunks tend towards. Boundaries are rarely found before it and quickly after it.
+    pub avg_size: usize,
+    pub max_size: usize,
+    pub algorithm: Algorithm,
+}
+
+impl Default for ChunkerOptions {
+    fn default() -> ChunkerOptions {
+        ChunkerOptions { min_size: 2 * 1024, avg_size: 8 * 1024, max_size: 64 * 1024, algorithm: Algorithm::Sha256 }
+    }
+}
+
+/// One chunk of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub offset: u64,
+    pub len: usize,
+    pub digest: Vec<u8>,
+}
+
+/// Finds chunk boundaries for one set of options.
+struct Chunker {
+    min_size: usize,
+    avg_size: usize,
+    max_size: usize,
+    /// Harder to match, used below the average size.
+    mask_small: u64,
+    /// Easier to match, used above it.
+    mask_large: u64,
+}
+
+impl Chunker {
+    fn new(options: &ChunkerOptions) -> Result<Chunker, Error> {
+        if options.min_size == 0 || options.min_size > options.avg_size || options.avg_size > options.max_size {
+            return Err(Error::new(
+                ErrorCode::InvalidArgument,
+                format!("chunk sizes must satisfy 0 < min <= avg <= max, not {}, {}, {}", options.min_size, options.avg_size, options.max_size),
+            ));
+        }
+        // One bit more or less than log2 of the average, FastCDC's normalisation level 1. The
+        // mask tests the top bits, which the shifting hash has mixed the most.
+        let bits = (usize::BITS - 1 - options.avg_size.leading_zeros()).clamp(1, 62);
+        Ok(Chunker {
+            min_size: options.min_size,
+            avg_size: options.avg_size,
+            max_size: options.max_size,
+            mask_small: !0u64 << (64 - (bits + 1)),
+            mask_large: !0u64 << (64 - (bits - 1).max(1)),
+        })
+    }
+
+    /// Returns the length of the first chunk of `data`. Unless `data` holds the rest of the input,
+    /// it must be at least `max_size` bytes long.
+    fn cut_point(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min_size {
+            return data.len();
+        }
+        let end = data.len().min(self.max_size);
+        let normal = end.min(self.avg_size);
+        let mut hash = 0u64;
+        for (i, byte) in data.iter().enumerate().take(end).skip(self.min_size) {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+            let mask = if i < normal { self.mask_small } else { self.mask_large };
+            if hash & mask == 0 {
+                return i + 1;
+            }
+        }
+        end
+    }
+}
+
+/// Splits everything `reader` yields into chunks and digests each of them.
+pub fn chunk_reader<R: Read>(reader: &mut R, options: &ChunkerOptions) -> Result<Vec<Chunk>, Error> {
+    let chunker = Chunker::new(options)?;
+    let mut chunks = Vec::new();
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let mut eof = false;
+    loop {
+        // Keep a whole maximum sized chunk in the buffer, dropping what's been chunked only when
+        // refilling so it isn't moved once per chunk
+        if !eof && buffer.len() - start < chunker.max_size {
+            buffer.drain(..start);
+            start = 0;
+        }
+        while !eof && buffer.len() < chunker.max_size {
+            let filled = buffer.len();
+            buffer.resize(filled + CHUNK_SIZE.max(chunker.max_size), 0);
+            match reader.read(&mut buffer[filled..]) {
+                Ok(count) => {
+                    buffer.truncate(filled + count);
+                    eof = count == 0;
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => buffer.truncate(filled),
+                Err(err) => return Err(Error::from(err)),
+            }
+        }
+        if start == buffer.len() {
+            break;
+        }
+        let len = chunker.cut_point(&buffer[start..]);
+        let mut hasher = Hasher::new(options.algorithm);
+        hasher.update(&buffer[start..start + len]);
+        chunks.push(Chunk { offset, len, digest: hasher.finalize() });
+        offset += len as u64;
+        start += len;
+    }
+    Ok(chunks)
+}
+
+/// Splits the file at `path` into chunks, see `chunk_reader`.
+pub fn chunk_file<P: AsRef<Path>>(path: P, options: &ChunkerOptions) -> Result<Vec<Chunk>, Error> {
+    let path = path.as_ref();
+    let to_error = |err| Error::from_io(&err, &path.to_string_lossy());
+    let mut file = File::open(path).map_err(to_error)?;
+    chunk_reader(&mut file, options).map_err(|err| Error::new(err.code, format!("{}: {}", path.to_string_lossy(), err.message)))
+}
+
+/// Where a chunk was found: which of the files and at what offset in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkRef {
+    pub file: usize,
+    pub offset: u64,
+}
+
+/// A chunk found more than once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedChunk {
+    pub digest: Vec<u8>,
+    pub len: usize,
+    pub refs: Vec<ChunkRef>,
+}
+
+/// How much of a set of files is duplicated at the chunk level.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DedupReport {
+    /// The size of all the files together.
+    pub total_bytes: u64,
+    /// What they would take with every chunk stored once.
+    pub unique_bytes: u64,
+    /// Chunks seen more than once, within a file or across files, in the order first seen.
+    pub shared: Vec<SharedChunk>,
+}
+
+/// Chunks every file in `paths` on up to `workers` threads, 0 meaning one per CPU, and reports the
+/// chunks they have in common. Fails if any file can't be read.
+pub fn find_shared_chunks<P: AsRef<Path> + Sync>(paths: &[P], options: &ChunkerOptions, workers: usize) -> Result<DedupReport, Error> {
+    Chunker::new(options)?;
+    let files = batch::run_pool(paths, workers, |path| chunk_file(path, options));
+
+    let mut report = DedupReport::default();
+    let mut seen: HashMap<Vec<u8>, SharedChunk> = HashMap::new();
+    let mut order = Vec::new();
+    for (file, chunks) in files.into_iter().enumerate() {
+        for chunk in chunks? {
+            report.total_bytes += chunk.len as u64;
+            let chunk_ref = ChunkRef { file, offset: chunk.offset };
+            if let Some(shared) = seen.get_mut(&chunk.digest) {
+                shared.refs.push(chunk_ref);
+                continue;
+            }
+            report.unique_bytes += chunk.len as u64;
+            order.push(chunk.digest.clone());
+            seen.insert(chunk.digest.clone(), SharedChunk { digest: chunk.digest, len: chunk.len, refs: vec![chunk_ref] });
+        }
+    }
+    report.shared = order
+        .into_iter()
+        .filter_map(|digest| seen.remove(&digest))
+        .filter(|chunk| chunk.refs.len() > 1)
+        .collect();
+    Ok(report)
+}
+
+/// Chunking options for the C API, see `ChunkerOptions`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CChunkerOptions {
+    pub min_size: usize,
+    pub avg_size: usize,
+    pub max_size: usize,
+    /// One of the `Algorithm` values.
+    pub algorithm: c_int,
+}
+
+impl CChunkerOptions {
+    pub fn to_options(self) -> Result<ChunkerOptions, Error> {
+        Ok(ChunkerOptions { min_size: self.min_size, avg_size: self.avg_size, max_size: self.max_size, algorithm: algorithm_from_c(self.algorithm)? })
+    }
+}
+
+/// One chunk of a `CChunkList`.
+#[repr(C)]
+pub struct CChunk {
+    pub offset: u64,
+    pub len: usize,
+    /// Lowercase hex digest.
+    pub digest: *mut c_char,
+}
+
+/// The result of `get_file_chunks`, released with `release_chunk_list`.
+#[repr(C)]
+pub struct CChunkList {
+    pub chunks: *mut CChunk,
+    pub count: usize,
+}
+
+/// One place a `CSharedChunk` was found.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CChunkRef {
+    /// Index into the paths passed to `get_shared_chunks`.
+    pub file: usize,
+    pub offset: u64,
+}
+
+/// A chunk found more than once.
+#[repr(C)]
+pub struct CSharedChunk {
+    /// Lowercase hex digest.
+    pub digest: *mut c_char,
+    pub len: usize,
+    pub refs: *mut CChunkRef,
+    pub ref_count: usize,
+}
+
+/// The result of `get_shared_chunks`, released with `release_dedup_report`.
+#[repr(C)]
+pub struct CDedupReport {
+    pub total_bytes: u64,
+    pub unique_bytes: u64,
+    pub chunks: *mut CSharedChunk,
+    pub count: usize,
+}
+
+unsafe fn options_from_c(options: *const CChunkerOptions) -> Result<ChunkerOptions, Error> {
+    if options.is_null() {
+        Ok(ChunkerOptions::default())
+    } else {
+        (*options).to_options()
+    }
+}
+
+fn hex_c_string(digest: &[u8]) -> *mut c_char {
+    bytes_into_c_string(checksum::to_hex(digest).into_bytes())
+}
+
+fn to_c_chunk_list(chunks: Vec<Chunk>) -> *mut CChunkList {
+    let chunks = chunks.into_iter().map(|chunk| CChunk { offset: chunk.offset, len: chunk.len, digest: hex_c_string(&chunk.digest) }).collect();
+    let (chunks, count) = into_c_array(chunks);
+    Box::into_raw(Box::new(CChunkList { chunks, count }))
+}
+
+fn to_c_dedup_report(report: DedupReport) -> *mut CDedupReport {
+    let chunks = report
+        .shared
+        .into_iter()
+        .map(|chunk| {
+            let refs = chunk.refs.iter().map(|r| CChunkRef { file: r.file, offset: r.offset }).collect();
+            let (refs, ref_count) = into_c_array(refs);
+            CSharedChunk { digest: hex_c_string(&chunk.digest), len: chunk.len, refs, ref_count }
+        })
+        .collect();
+    let (chunks, count) = into_c_array(chunks);
+    Box::into_raw(Box::new(CDedupReport { total_bytes: report.total_bytes, unique_bytes: report.unique_bytes, chunks, count }))
+}
+
+/// Splits the file at `filepath` into content-defined chunks and returns their offsets, lengths
+/// and digests, or NULL on failure. A NULL `options` uses 2/8/64 KiB chunks and SHA-256. The
+/// result must be freed with `release_chunk_list`.
+///
+/// # Safety
+///
+/// `filepath` must be NULL or point to a valid NUL terminated string and `options` must be NULL or
+/// point to a valid `CChunkerOptions`.
+#[no_mangle]
+pub unsafe extern "C" fn get_file_chunks(filepath: *const c_char, options: *const CChunkerOptions) -> *mut CChunkList {
+    error::guard(ptr::null_mut(), || {
+        let filepath = path_from_c(filepath)?;
+        chunk_file(filepath, &options_from_c(options)?).map(to_c_chunk_list)
+    })
+}
+
+/// Frees a list returned by `get_file_chunks`.
+///
+/// # Safety
+///
+/// `list` must be NULL or a pointer returned by `get_file_chunks` that has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn release_chunk_list(list: *mut CChunkList) {
+    if list.is_null() {
+        return;
+    }
+    error::guard_silent((), || {
+        let list = Box::from_raw(list);
+        for chunk in from_c_array(list.chunks, list.count).iter() {
+            drop(CString::from_raw(chunk.digest));
+        }
+    })
+}
+
+/// Chunks the `count` files in `paths` on one worker thread per CPU and reports the chunks found
+/// more than once, with where each copy is. Returns NULL if any file can't be read. The result
+/// must be freed with `release_dedup_report`.
+///
+/// # Safety
+///
+/// `paths` must point to `count` valid NUL terminated strings and `options` must be NULL or point
+/// to a valid `CChunkerOptions`.
+#[no_mangle]
+pub unsafe extern "C" fn get_shared_chunks(paths: *const *const c_char, count: usize, options: *const CChunkerOptions) -> *mut CDedupReport {
+    error::guard(ptr::null_mut(), || {
+        let options = options_from_c(options)?;
+        if count == 0 {
+            return Ok(to_c_dedup_report(DedupReport::default()));
+        }
+        if paths.is_null() {
+            return Err(Error::new(ErrorCode::NullArgument, "paths is NULL"));
+        }
+        let paths = slice::from_raw_parts(paths, count).iter().map(|p| path_from_c(*p)).collect::<Result<Vec<_>, _>>()?;
+        find_shared_chunks(&paths, &options, 0).map(to_c_dedup_report)
+    })
+}
+
+/// Frees a report returned by `get_shared_chunks`.
+///
+/// # Safety
+///
+/// `report` must be NULL or a pointer returned by `get_shared_chunks` that has not been released
+/// yet.
+#[no_mangle]
+pub unsafe extern "C" fn release_dedup_report(report: *mut CDedupReport) {
+    if report.is_null() {
+        return;
+    }
+    error::guard_silent((), || {
+        let report = Box::from_raw(report);
+        for chunk in from_c_array(report.chunks, report.count).iter() {
+            drop(CString::from_raw(chunk.digest));
+            drop(from_c_array(chunk.refs, chunk.ref_count));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::ffi::CStr;
+    use std::fs;
+    use std::io::Cursor;
+
+    use test_util::pseudo_random;
+
+    fn chunk(data: &[u8], options: &ChunkerOptions) -> Vec<Chunk> {
+        chunk_reader(&mut Cursor::new(data), options).unwrap()
+    }
+
+    #[test]
+    fn chunks_cover_the_input_within_limits() {
+        let data = pseudo_random(1_000_000, 1);
+        let options = ChunkerOptions::default();
+        let chunks = chunk(&data, &options);
+        let mut offset = 0;
+        for (i, c) in chunks.iter().enumerate() {
+            assert_eq!(c.offset, offset as u64);
+            assert!(c.len <= options.max_size);
+            assert!(c.len >= options.min_size || i == chunks.len() - 1);
+            assert_eq!(c.digest, checksum::checksum_bytes(&data[offset..offset + c.len], Algorithm::Sha256));
+            offset += c.len;
+        }
+        assert_eq!(offset, data.len());
+        // The average lands somewhere near the target
+        let average = data.len() / chunks.len();
+        assert!(average > 4 * 1024 && average < 16 * 1024, "average chunk {}", average);
+        assert_eq!(chunk(&data, &options), chunks);
+    }
+
+    #[test]
+    fn boundaries_resynchronise_after_an_edit() {
+        let old = pseudo_random(500_000, 2);
+        let mut new = old.clone();
+        new.splice(100_000..100_000, b"a few inserted bytes".iter().cloned());
+        let report = {
+            let dir = temp_dir().join("file_checksum_chunking_edit");
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("old"), &old).unwrap();
+            fs::write(dir.join("new"), &new).unwrap();
+            find_shared_chunks(&[dir.join("old"), dir.join("new")], &ChunkerOptions::default(), 2).unwrap()
+        };
+        assert_eq!(report.total_bytes, (old.len() + new.len()) as u64);
+        // Only the chunk or two around the insertion differ
+        let extra = report.unique_bytes - old.len() as u64;
+        assert!(extra < 3 * 64 * 1024, "{} extra unique bytes", extra);
+        assert!(report.shared.iter().all(|c| c.refs.len() == 2 && c.refs[0].file == 0 && c.refs[1].file == 1));
+    }
+
+    #[test]
+    fn repeats_within_a_file() {
+        let block = pseudo_random(100_000, 3);
+        let data = [&block[..], &block[..], &block[..]].concat();
+        let options = ChunkerOptions { min_size: 256, avg_size: 1024, max_size: 4096, algorithm: Algorithm::Md5 };
+        let dir = temp_dir().join("file_checksum_chunking_repeats");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("data"), &data).unwrap();
+        let report = find_shared_chunks(&[dir.join("data")], &options, 1).unwrap();
+        assert!(report.unique_bytes < block.len() as u64 + 3 * 4096);
+        assert!(report.shared.iter().any(|c| c.refs.len() == 3));
+    }
+
+    #[test]
+    fn edge_cases() {
+        assert!(chunk(b"", &ChunkerOptions::default()).is_empty());
+        assert_eq!(chunk(b"abc", &ChunkerOptions::default()).len(), 1);
+        let bad = ChunkerOptions { min_size: 10, avg_size: 5, max_size: 20, algorithm: Algorithm::Md5 };
+        assert_eq!(chunk_reader(&mut Cursor::new(b"abc"), &bad).unwrap_err().code, ErrorCode::InvalidArgument);
+        assert_eq!(find_shared_chunks(&["/this/path/does/not/exist"], &ChunkerOptions::default(), 1).unwrap_err().code, ErrorCode::NotFound);
+    }
+
+    #[test]
+    fn c_api() {
+        let dir = temp_dir().join("file_checksum_chunking_c");
+        fs::create_dir_all(&dir).unwrap();
+        let data = pseudo_random(50_000, 4);
+        fs::write(dir.join("a"), &data).unwrap();
+        fs::write(dir.join("b"), [&data[..], b"tail"].concat()).unwrap();
+        let a = CString::new(dir.join("a").to_str().unwrap()).unwrap();
+        let b = CString::new(dir.join("b").to_str().unwrap()).unwrap();
+        let options = CChunkerOptions { min_size: 512, avg_size: 2048, max_size: 8192, algorithm: Algorithm::Sha1 as c_int };
+        unsafe {
+            let list = get_file_chunks(a.as_ptr(), &options);
+            assert!(!list.is_null());
+            let chunks = slice::from_raw_parts((*list).chunks, (*list).count);
+            assert_eq!(chunks.iter().map(|c| c.len).sum::<usize>(), data.len());
+            let first = &data[..chunks[0].len];
+            assert_eq!(CStr::from_ptr(chunks[0].digest).to_str().unwrap(), checksum::to_hex(&checksum::checksum_bytes(first, Algorithm::Sha1)));
+            release_chunk_list(list);
+
+            let paths = [a.as_ptr(), b.as_ptr()];
+            let report = get_shared_chunks(paths.as_ptr(), 2, &options);
+            assert!(!report.is_null());
+            assert_eq!((*report).total_bytes, 2 * data.len() as u64 + 4);
+            assert!((*report).count > 0);
+            let shared = &*(*report).chunks;
+            let refs = slice::from_raw_parts(shared.refs, shared.ref_count);
+            assert_eq!((refs[0].file, refs[0].offset, refs[1].file, refs[1].offset), (0, 0, 1, 0));
+            release_dedup_report(report);
+
+            let bad = CChunkerOptions { max_size: 100, ..options };
+            assert!(get_file_chunks(a.as_ptr(), &bad).is_null());
+            assert_eq!(error::last_error_code(), ErrorCode::InvalidArgument);
+            assert!(get_shared_chunks(ptr::null(), 1, ptr::null()).is_null());
+            assert_eq!(error::last_error_code(), ErrorCode::NullArgument);
+        }
+    }
+}
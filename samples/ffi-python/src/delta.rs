@@ -0,0 +1,622 @@
+//! rsync style deltas. The receiver describes the file it already has with a signature, a weak
+//! rolling checksum and a strong digest for every block. The sender slides a window over its new
+//! file looking for those blocks and writes a delta of block copies and literal bytes, which the
+//! receiver applies to its old file to rebuild the new one. Only the changes cross the wire.
+//!
+//! Signatures and deltas are streamed so files of any size can be handled in constant memory,
+//! apart from the signature itself.
+//!
+//! Signature layout, integers big-endian:
+//!
+//! ```text
+//! "FCS1" | block size u32 | algorithm u8 | file length u64 | (weak u32 | strong digest)*
+//! ```
+//!
+//! Delta layout:
+//!
+//! ```text
+//! "FCD1" | block size u32 | algorithm u8 | op* | 0x00 | digest of the new file
+//! op = 0x01 first block u64 | block count u64
+//!    | 0x02 length u32 | literal bytes
+//! ```
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use libc::{c_char, c_int};
+
+use checksum::{Algorithm, Hasher, CHUNK_SIZE};
+use error::{self, Error, ErrorCode};
+use ffi::{algorithm_from_c, path_from_c};
+
+/// Block size used when none is asked for. rsync picks about the square root of the file size;
+/// a fixed size suits builds of a few GB well enough.
+pub const DEFAULT_BLOCK_SIZE: usize = 4096;
+
+const SIGNATURE_MAGIC: &[u8; 4] = b"FCS1";
+const DELTA_MAGIC: &[u8; 4] = b"FCD1";
+const OP_END: u8 = 0;
+const OP_COPY: u8 = 1;
+const OP_LITERAL: u8 = 2;
+/// Literal runs are split so neither side holds more than this much unmatched data at once.
+const MAX_LITERAL: usize = 1024 * 1024;
+
+/// rsync's weak checksum, a variant of Adler-32 with both sums kept modulo 2^16 rather than a
+/// prime, so a window can be moved along by a byte in constant time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RollingChecksum {
+    a: u32,
+    b: u32,
+    len: usize,
+}
+
+impl RollingChecksum {
+    pub fn new(data: &[u8]) -> RollingChecksum {
+        let mut checksum = RollingChecksum::default();
+        checksum.update(data);
+        checksum
+    }
+
+    /// Appends `data` to the window.
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.a = self.a.wrapping_add(u32::from(*byte));
+            self.b = self.b.wrapping_add(self.a);
+        }
+        self.len += data.len();
+    }
+
+    /// Moves the window along by one byte, dropping `out` from the front and adding `next` at the
+    /// back.
+    pub fn roll(&mut self, out: u8, next: u8) {
+        let out = u32::from(out);
+        self.a = self.a.wrapping_sub(out).wrapping_add(u32::from(next));
+        self.b = self.b.wrapping_sub((self.len as u32).wrapping_mul(out)).wrapping_add(self.a);
+    }
+
+    pub fn value(&self) -> u32 {
+        (self.a & 0xffff) | (self.b << 16)
+    }
+}
+
+/// The checksums of one block of the old file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockSignature {
+    pub weak: u32,
+    pub strong: Vec<u8>,
+}
+
+/// Describes a file as a list of blocks. All blocks are `block_size` bytes long except perhaps
+/// the last.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub block_size: usize,
+    pub algorithm: Algorithm,
+    pub file_len: u64,
+    pub blocks: Vec<BlockSignature>,
+}
+
+/// What `write_delta` found, in bytes of the new file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DeltaStats {
+    /// Bytes the receiver already has and copies from its old file.
+    pub copied: u64,
+    /// Bytes sent as they are.
+    pub literal: u64,
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorCode::InvalidDelta, message)
+}
+
+fn strong_digest(data: &[u8], algorithm: Algorithm) -> Vec<u8> {
+    let mut hasher = Hasher::new(algorithm);
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Reads until `buffer` is full or the reader is exhausted, returning how much was read.
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(count) => filled += count,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8, Error> {
+    let mut value = [0; 1];
+    reader.read_exact(&mut value).map_err(truncated)?;
+    Ok(value[0])
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, Error> {
+    let mut value = [0; 4];
+    reader.read_exact(&mut value).map_err(truncated)?;
+    Ok(u32::from_be_bytes(value))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, Error> {
+    let mut value = [0; 8];
+    reader.read_exact(&mut value).map_err(truncated)?;
+    Ok(u64::from_be_bytes(value))
+}
+
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> Result<Vec<u8>, Error> {
+    let mut value = vec![0; len];
+    reader.read_exact(&mut value).map_err(truncated)?;
+    Ok(value)
+}
+
+fn truncated(err: io::Error) -> Error {
+    if err.kind() == io::ErrorKind::UnexpectedEof {
+        invalid("unexpected end of data")
+    } else {
+        Error::from(err)
+    }
+}
+
+/// Reads the header shared by signatures and deltas.
+fn read_header<R: Read>(reader: &mut R, magic: &[u8; 4]) -> Result<(usize, Algorithm), Error> {
+    if read_bytes(reader, 4)? != magic {
+        return Err(invalid(if magic == SIGNATURE_MAGIC { "not a signature" } else { "not a delta" }));
+    }
+    let block_size = read_u32(reader)? as usize;
+    let algorithm = Algorithm::from_raw(i32::from(read_u8(reader)?)).ok_or_else(|| invalid("unknown algorithm"))?;
+    if block_size == 0 {
+        return Err(invalid("block size is 0"));
+    }
+    Ok((block_size, algorithm))
+}
+
+fn write_header<W: Write>(out: &mut W, magic: &[u8; 4], block_size: usize, algorithm: Algorithm) -> io::Result<()> {
+    out.write_all(magic)?;
+    out.write_all(&(block_size as u32).to_be_bytes())?;
+    out.write_all(&[algorithm as u8])
+}
+
+fn check_block_size(block_size: usize) -> Result<(), Error> {
+    if block_size == 0 || block_size > u32::MAX as usize {
+        return Err(Error::new(ErrorCode::InvalidArgument, format!("invalid block size {}", block_size)));
+    }
+    Ok(())
+}
+
+impl Signature {
+    /// Length of block `index`, which is only short for the last block.
+    pub fn block_len(&self, index: usize) -> usize {
+        let start = index as u64 * self.block_size as u64;
+        (self.file_len - start).min(self.block_size as u64) as usize
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write_header(out, SIGNATURE_MAGIC, self.block_size, self.algorithm)?;
+        out.write_all(&self.file_len.to_be_bytes())?;
+        for block in self.blocks.iter() {
+            out.write_all(&block.weak.to_be_bytes())?;
+            out.write_all(&block.strong)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Signature, Error> {
+        let (block_size, algorithm) = read_header(reader, SIGNATURE_MAGIC)?;
+        let file_len = read_u64(reader)?;
+        let count = file_len.div_ceil(block_size as u64);
+        let mut blocks = Vec::new();
+        for _ in 0..count {
+            let weak = read_u32(reader)?;
+            let strong = read_bytes(reader, algorithm.digest_len())?;
+            blocks.push(BlockSignature { weak, strong });
+        }
+        if read_full(reader, &mut [0; 1])? != 0 {
+            return Err(invalid("trailing data after signature"));
+        }
+        Ok(Signature { block_size, algorithm, file_len, blocks })
+    }
+}
+
+/// Computes the signature of everything `reader` yields, in blocks of `block_size` bytes with
+/// `algorithm` as the strong digest.
+pub fn signature<R: Read>(reader: &mut R, block_size: usize, algorithm: Algorithm) -> Result<Signature, Error> {
+    check_block_size(block_size)?;
+    let mut blocks = Vec::new();
+    let mut file_len = 0;
+    let mut block = vec![0; block_size];
+    loop {
+        let len = read_full(reader, &mut block)?;
+        if len == 0 {
+            break;
+        }
+        file_len += len as u64;
+        blocks.push(BlockSignature { weak: RollingChecksum::new(&block[..len]).value(), strong: strong_digest(&block[..len], algorithm) });
+        if len < block_size {
+            break;
+        }
+    }
+    Ok(Signature { block_size, algorithm, file_len, blocks })
+}
+
+/// Writes delta ops, merging runs of consecutive blocks into one copy.
+struct DeltaWriter<'a, W: Write + 'a> {
+    out: &'a mut W,
+    copy: Option<(u64, u64)>,
+    literal: Vec<u8>,
+    stats: DeltaStats,
+}
+
+impl<'a, W: Write> DeltaWriter<'a, W> {
+    fn flush_copy(&mut self) -> io::Result<()> {
+        if let Some((first, count)) = self.copy.take() {
+            self.out.write_all(&[OP_COPY])?;
+            self.out.write_all(&first.to_be_bytes())?;
+            self.out.write_all(&count.to_be_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn flush_literal(&mut self) -> io::Result<()> {
+        if !self.literal.is_empty() {
+            self.out.write_all(&[OP_LITERAL])?;
+            self.out.write_all(&(self.literal.len() as u32).to_be_bytes())?;
+            self.out.write_all(&self.literal)?;
+            self.stats.literal += self.literal.len() as u64;
+            self.literal.clear();
+        }
+        Ok(())
+    }
+
+    fn copy(&mut self, index: usize, len: usize) -> io::Result<()> {
+        self.flush_literal()?;
+        self.stats.copied += len as u64;
+        let index = index as u64;
+        match self.copy {
+            Some((first, ref mut count)) if first + *count == index => *count += 1,
+            _ => {
+                self.flush_copy()?;
+                self.copy = Some((index, 1));
+            }
+        }
+        Ok(())
+    }
+
+    fn literal(&mut self, data: &[u8]) -> io::Result<()> {
+        self.flush_copy()?;
+        for chunk in data.chunks(MAX_LITERAL) {
+            if self.literal.len() + chunk.len() > MAX_LITERAL {
+                self.flush_literal()?;
+            }
+            self.literal.extend_from_slice(chunk);
+        }
+        Ok(())
+    }
+}
+
+/// Compares what `new` yields against `signature` and writes a delta that turns the signed file
+/// into it.
+pub fn write_delta<R: Read, W: Write>(signature: &Signature, new: &mut R, out: &mut W) -> Result<DeltaStats, Error> {
+    let block_size = signature.block_size;
+    // Only whole blocks are looked up while rolling; a short last block can only match at the end
+    let mut index: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (i, block) in signature.blocks.iter().enumerate() {
+        if signature.block_len(i) == block_size {
+            index.entry(block.weak).or_default().push(i);
+        }
+    }
+    let find = |window: &[u8], weak: u32| -> Option<usize> {
+        let candidates = index.get(&weak)?;
+        let strong = strong_digest(window, signature.algorithm);
+        candidates.iter().cloned().find(|i| signature.blocks[*i].strong == strong)
+    };
+
+    write_header(out, DELTA_MAGIC, block_size, signature.algorithm)?;
+    let mut writer = DeltaWriter { out, copy: None, literal: Vec::new(), stats: DeltaStats::default() };
+    let mut whole = Hasher::new(signature.algorithm);
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut start = 0;
+    let mut eof = false;
+    let mut rolling: Option<RollingChecksum> = None;
+    loop {
+        // Keep a whole window and the byte after it in the buffer
+        if !eof && buffer.len() - start <= block_size {
+            buffer.drain(..start);
+            start = 0;
+            let filled = buffer.len();
+            buffer.resize(filled + CHUNK_SIZE.max(block_size), 0);
+            let count = read_full(new, &mut buffer[filled..])?;
+            buffer.truncate(filled + count);
+            whole.update(&buffer[filled..]);
+            eof = count == 0;
+            continue;
+        }
+
+        let available = buffer.len() - start;
+        if available < block_size {
+            // The tail can only match the old file's short last block
+            let tail = &buffer[start..];
+            let last = signature.blocks.len().wrapping_sub(1);
+            let matches = !tail.is_empty()
+                && signature.blocks.last().is_some_and(|block| {
+                    signature.block_len(last) == tail.len()
+                        && block.weak == RollingChecksum::new(tail).value()
+                        && block.strong == strong_digest(tail, signature.algorithm)
+                });
+            if matches {
+                writer.copy(last, tail.len())?;
+            } else {
+                writer.literal(tail)?;
+            }
+            break;
+        }
+
+        let window = &buffer[start..start + block_size];
+        let checksum = *rolling.get_or_insert_with(|| RollingChecksum::new(window));
+        if let Some(i) = find(window, checksum.value()) {
+            writer.copy(i, block_size)?;
+            start += block_size;
+            rolling = None;
+            continue;
+        }
+
+        let out = buffer[start];
+        writer.literal(&[out])?;
+        rolling = match (rolling, buffer.get(start + block_size)) {
+            (Some(mut checksum), Some(next)) => {
+                checksum.roll(out, *next);
+                Some(checksum)
+            }
+            _ => None,
+        };
+        start += 1;
+    }
+    writer.flush_copy()?;
+    writer.flush_literal()?;
+    let stats = writer.stats;
+    let out = writer.out;
+    out.write_all(&[OP_END])?;
+    out.write_all(&whole.finalize())?;
+    Ok(stats)
+}
+
+/// Rebuilds the new file from the `old` file the signature was made from and a delta, writing it
+/// to `out`. The result is checked against the digest at the end of the delta. Returns the number
+/// of bytes written.
+pub fn apply_delta<O, R, W>(old: &mut O, delta: &mut R, out: &mut W) -> Result<u64, Error>
+where
+    O: Read + Seek,
+    R: Read,
+    W: Write,
+{
+    let (block_size, algorithm) = read_header(delta, DELTA_MAGIC)?;
+    let old_len = old.seek(SeekFrom::End(0))?;
+    let mut whole = Hasher::new(algorithm);
+    let mut written = 0;
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        match read_u8(delta)? {
+            OP_COPY => {
+                let (first, count) = (read_u64(delta)?, read_u64(delta)?);
+                let start = first.checked_mul(block_size as u64).filter(|start| *start < old_len);
+                let start = start.ok_or_else(|| invalid("copy starts past the end of the old file"))?;
+                let len = count.saturating_mul(block_size as u64).min(old_len - start);
+                old.seek(SeekFrom::Start(start))?;
+                let mut remaining = len;
+                while remaining > 0 {
+                    let chunk = &mut buffer[..remaining.min(CHUNK_SIZE as u64) as usize];
+                    old.read_exact(chunk).map_err(|_| invalid("the old file changed while applying the delta"))?;
+                    whole.update(chunk);
+                    out.write_all(chunk)?;
+                    remaining -= chunk.len() as u64;
+                }
+                written += len;
+            }
+            OP_LITERAL => {
+                let len = read_u32(delta)? as usize;
+                if len > MAX_LITERAL {
+                    return Err(invalid("literal is too long"));
+                }
+                let literal = read_bytes(delta, len)?;
+                whole.update(&literal);
+                out.write_all(&literal)?;
+                written += len as u64;
+            }
+            OP_END => break,
+            op => return Err(invalid(&format!("unknown op {}", op))),
+        }
+    }
+    if read_bytes(delta, algorithm.digest_len())? != whole.finalize() {
+        return Err(invalid("the result doesn't match the delta, the old file isn't the one that was signed"));
+    }
+    Ok(written)
+}
+
+fn open(path: &Path) -> Result<File, Error> {
+    File::open(path).map_err(|err| Error::from_io(&err, &path.to_string_lossy()))
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>, Error> {
+    File::create(path).map(BufWriter::new).map_err(|err| Error::from_io(&err, &path.to_string_lossy()))
+}
+
+unsafe fn signature_files(old_path: *const c_char, signature_path: *const c_char, block_size: usize, algorithm: c_int) -> Result<(), Error> {
+    let (old_path, signature_path) = (path_from_c(old_path)?, path_from_c(signature_path)?);
+    let algorithm = algorithm_from_c(algorithm)?;
+    let block_size = if block_size == 0 { DEFAULT_BLOCK_SIZE } else { block_size };
+    let signature = signature(&mut BufReader::new(open(old_path)?), block_size, algorithm)?;
+    let mut out = create(signature_path)?;
+    signature.write_to(&mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+unsafe fn delta_files(signature_path: *const c_char, new_path: *const c_char, delta_path: *const c_char) -> Result<(), Error> {
+    let (signature_path, new_path, delta_path) = (path_from_c(signature_path)?, path_from_c(new_path)?, path_from_c(delta_path)?);
+    let signature = Signature::read_from(&mut BufReader::new(open(signature_path)?))?;
+    let mut out = create(delta_path)?;
+    write_delta(&signature, &mut open(new_path)?, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+unsafe fn patch_files(old_path: *const c_char, delta_path: *const c_char, out_path: *const c_char) -> Result<(), Error> {
+    let (old_path, delta_path, out_path) = (path_from_c(old_path)?, path_from_c(delta_path)?, path_from_c(out_path)?);
+    let mut old = open(old_path)?;
+    let mut delta = BufReader::new(open(delta_path)?);
+    let mut out = create(out_path)?;
+    apply_delta(&mut old, &mut delta, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes the signature of the file at `old_path` to `signature_path`, in blocks of `block_size`
+/// bytes, or 4096 if it is 0, with `algorithm` as the strong digest. Returns 0 or an `ErrorCode`.
+///
+/// # Safety
+///
+/// Both paths must be NULL or point to valid NUL terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn file_checksum_signature(old_path: *const c_char, signature_path: *const c_char, block_size: usize, algorithm: c_int) -> c_int {
+    error::guard_status(|| signature_files(old_path, signature_path, block_size, algorithm)) as c_int
+}
+
+/// Writes the delta that turns the file described by the signature at `signature_path` into the
+/// file at `new_path` to `delta_path`. Returns 0 or an `ErrorCode`.
+///
+/// # Safety
+///
+/// All paths must be NULL or point to valid NUL terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn file_checksum_delta(signature_path: *const c_char, new_path: *const c_char, delta_path: *const c_char) -> c_int {
+    error::guard_status(|| delta_files(signature_path, new_path, delta_path)) as c_int
+}
+
+/// Applies the delta at `delta_path` to the file at `old_path`, which must be the file the
+/// signature was made from, writing the result to `out_path`. `out_path` must not be `old_path`.
+/// Returns 0, or an `ErrorCode` such as `InvalidDelta` if the delta is damaged or the rebuilt file
+/// doesn't match.
+///
+/// # Safety
+///
+/// All paths must be NULL or point to valid NUL terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn file_checksum_patch(old_path: *const c_char, delta_path: *const c_char, out_path: *const c_char) -> c_int {
+    error::guard_status(|| patch_files(old_path, delta_path, out_path)) as c_int
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::ffi::CString;
+    use std::fs;
+    use std::io::Cursor;
+    use std::ptr;
+
+    use test_util::pseudo_random;
+
+    fn round_trip(old: &[u8], new: &[u8], block_size: usize) -> DeltaStats {
+        let signature = signature(&mut Cursor::new(old), block_size, Algorithm::Md5).unwrap();
+        let mut encoded = Vec::new();
+        signature.write_to(&mut encoded).unwrap();
+        let signature = Signature::read_from(&mut Cursor::new(encoded)).unwrap();
+
+        let mut delta = Vec::new();
+        let stats = write_delta(&signature, &mut Cursor::new(new), &mut delta).unwrap();
+        let mut rebuilt = Vec::new();
+        let written = apply_delta(&mut Cursor::new(old), &mut Cursor::new(delta), &mut rebuilt).unwrap();
+        assert_eq!(rebuilt, new);
+        assert_eq!(written, new.len() as u64);
+        assert_eq!(stats.copied + stats.literal, new.len() as u64);
+        stats
+    }
+
+    #[test]
+    fn rolling_matches_fresh_checksum() {
+        let data = pseudo_random(1000, 1);
+        let mut rolling = RollingChecksum::new(&data[..100]);
+        for start in 1..900 {
+            rolling.roll(data[start - 1], data[start + 99]);
+            assert_eq!(rolling.value(), RollingChecksum::new(&data[start..start + 100]).value());
+        }
+        assert_eq!(RollingChecksum::new(b"").value(), 0);
+    }
+
+    #[test]
+    fn unchanged_file_is_all_copies() {
+        let data = pseudo_random(100_000, 2);
+        assert_eq!(round_trip(&data, &data, 1000), DeltaStats { copied: 100_000, literal: 0 });
+    }
+
+    #[test]
+    fn edits_send_only_the_changes() {
+        let old = pseudo_random(200_000, 3);
+        let mut new = old.clone();
+        new.splice(50_000..50_000, b"inserted".iter().cloned());
+        new.drain(120_000..121_500);
+        new[180_000] ^= 0xff;
+        let stats = round_trip(&old, &new, 1000);
+        // Each edit costs at most a couple of blocks
+        assert!(stats.literal < 6 * 1000, "{:?}", stats);
+
+        // Appending keeps every old block, including a short last one
+        let mut appended = old.clone();
+        appended.extend_from_slice(b"more");
+        assert_eq!(round_trip(&old, &appended, 1000).literal, 4);
+        assert_eq!(round_trip(&old[..1500], &old[..1500], 1000).literal, 0);
+    }
+
+    #[test]
+    fn edge_cases() {
+        let data = pseudo_random(5000, 4);
+        assert_eq!(round_trip(b"", &data, 512).literal, 5000);
+        assert_eq!(round_trip(&data, b"", 512), DeltaStats::default());
+        assert_eq!(round_trip(b"", b"", 512), DeltaStats::default());
+        // Blocks bigger than the read buffer
+        round_trip(&pseudo_random(300_000, 5), &pseudo_random(300_000, 5)[1..], 100_000);
+        assert_eq!(signature(&mut Cursor::new(&data), 0, Algorithm::Md5).unwrap_err().code, ErrorCode::InvalidArgument);
+    }
+
+    #[test]
+    fn damaged_deltas_are_rejected() {
+        let old = pseudo_random(10_000, 6);
+        let new = pseudo_random(10_000, 7);
+        let signature = signature(&mut Cursor::new(&old), 1000, Algorithm::Sha256).unwrap();
+        let mut delta = Vec::new();
+        write_delta(&signature, &mut Cursor::new(&old), &mut delta).unwrap();
+
+        let apply = |old: &[u8], delta: &[u8]| apply_delta(&mut Cursor::new(old), &mut Cursor::new(delta), &mut Vec::new());
+        assert_eq!(apply(&new, &delta).unwrap_err().code, ErrorCode::InvalidDelta);
+        assert_eq!(apply(&old, &delta[..delta.len() - 1]).unwrap_err().code, ErrorCode::InvalidDelta);
+        assert_eq!(apply(&old, b"FCS1").unwrap_err().code, ErrorCode::InvalidDelta);
+        assert_eq!(Signature::read_from(&mut Cursor::new(&delta)).unwrap_err().code, ErrorCode::InvalidDelta);
+    }
+
+    #[test]
+    fn c_round_trip() {
+        let dir = temp_dir().join("file_checksum_delta");
+        fs::create_dir_all(&dir).unwrap();
+        let old = pseudo_random(50_000, 8);
+        let mut new = old.clone();
+        new.splice(10_000..10_000, b"changed".iter().cloned());
+        fs::write(dir.join("old"), &old).unwrap();
+        fs::write(dir.join("new"), &new).unwrap();
+
+        let path = |name: &str| CString::new(dir.join(name).to_str().unwrap()).unwrap();
+        unsafe {
+            assert_eq!(file_checksum_signature(path("old").as_ptr(), path("sig").as_ptr(), 0, Algorithm::Blake2b as c_int), 0);
+            assert_eq!(file_checksum_delta(path("sig").as_ptr(), path("new").as_ptr(), path("delta").as_ptr()), 0);
+            assert_eq!(file_checksum_patch(path("old").as_ptr(), path("delta").as_ptr(), path("out").as_ptr()), 0);
+            assert_eq!(file_checksum_patch(path("new").as_ptr(), path("delta").as_ptr(), path("bad").as_ptr()), ErrorCode::InvalidDelta as c_int);
+            assert_eq!(file_checksum_delta(path("new").as_ptr(), path("new").as_ptr(), path("delta").as_ptr()), ErrorCode::InvalidDelta as c_int);
+            assert_eq!(file_checksum_signature(ptr::null(), path("sig").as_ptr(), 0, 0), ErrorCode::NullArgument as c_int);
+        }
+        assert_eq!(fs::read(dir.join("out")).unwrap(), new);
+        assert!(fs::metadata(dir.join("delta")).unwrap().len() < 10_000);
+    }
+}
@@ -0,0 +1,417 @@
+//! Finding files with identical content under a set of directories. Files are compared by size,
+//! then by a digest of their first and last few KiB, and only files that still match are hashed
+//! in full, so most files are never read past their head and tail.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use libc::{c_char, c_int};
+
+use batch;
+use cache;
+use checksum::{self, Algorithm, Hasher};
+use error::{self, Error, ErrorCode};
+use ffi::{algorithm_from_c, bytes_into_c_string, from_c_array, into_c_array, path_from_c};
+use manifest::{self, ManifestOptions};
+
+/// How much of each end of a file goes into its partial digest.
+const PARTIAL_LEN: u64 = 4096;
+
+/// Controls which files `find_duplicates` compares. The default compares every non-empty file
+/// with SHA-256 without following links.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateOptions {
+    pub algorithm: Algorithm,
+    /// Follow symbolic links to files and directories. When false, links are skipped.
+    pub follow_symlinks: bool,
+    /// Skip files and directories whose name starts with a dot.
+    pub skip_hidden: bool,
+    /// Files smaller than this are ignored.
+    pub min_size: u64,
+}
+
+impl Default for DuplicateOptions {
+    fn default() -> DuplicateOptions {
+        DuplicateOptions { algorithm: Algorithm::default(), follow_symlinks: false, skip_hidden: false, min_size: 1 }
+    }
+}
+
+/// Files with the same content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    pub size: u64,
+    pub digest: Vec<u8>,
+    /// Sorted, at least two.
+    pub paths: Vec<PathBuf>,
+}
+
+impl DuplicateGroup {
+    /// The space that would be freed by keeping only one of the files.
+    pub fn wasted_bytes(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+/// The result of `find_duplicates`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DuplicateReport {
+    /// Groups with the most wasted space first.
+    pub groups: Vec<DuplicateGroup>,
+    /// Distinct regular files found under the roots.
+    pub files_scanned: usize,
+    /// How many of them were read in full, counting files small enough for their partial digest
+    /// to cover them as well as those whose partial digests matched.
+    pub files_fully_hashed: usize,
+    /// Files that disappeared or couldn't be read during the scan. They are left out of the groups.
+    pub errors: Vec<Error>,
+}
+
+impl DuplicateReport {
+    pub fn wasted_bytes(&self) -> u64 {
+        self.groups.iter().map(DuplicateGroup::wasted_bytes).sum()
+    }
+}
+
+/// Identifies the file behind a path, so hard links and roots given twice aren't reported as
+/// copies of themselves.
+#[cfg(unix)]
+fn file_id(metadata: &Metadata, _path: &Path) -> (u64, u64, PathBuf) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino(), PathBuf::new())
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata, path: &Path) -> (u64, u64, PathBuf) {
+    (0, 0, fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()))
+}
+
+/// Digests the first and last `PARTIAL_LEN` bytes of a file of `size` bytes. A file no bigger than
+/// both together is read whole, which makes this its full digest.
+fn partial_digest(path: &Path, size: u64, algorithm: Algorithm) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let mut hasher = Hasher::new(algorithm);
+    let mut buffer = vec![0u8; size.min(PARTIAL_LEN * 2) as usize];
+    if size <= PARTIAL_LEN * 2 {
+        file.read_exact(&mut buffer)?;
+    } else {
+        let (head, tail) = buffer.split_at_mut(PARTIAL_LEN as usize);
+        file.read_exact(head)?;
+        file.seek(SeekFrom::Start(size - PARTIAL_LEN))?;
+        file.read_exact(tail)?;
+    }
+    hasher.update(&buffer);
+    Ok(hasher.finalize())
+}
+
+/// A file still in the running, with its latest digest.
+struct Candidate {
+    path: PathBuf,
+    size: u64,
+    digest: Vec<u8>,
+}
+
+/// Digests every candidate with `f` on the pool and returns the sets that still match, in the
+/// order their first member was seen. Files that fail are dropped and their errors kept.
+fn refine<F>(candidates: Vec<Candidate>, workers: usize, errors: &mut Vec<Error>, f: F) -> Vec<Vec<Candidate>>
+where
+    F: Fn(&Candidate) -> io::Result<Vec<u8>> + Sync,
+{
+    let results = batch::run_pool(&candidates, workers, |candidate| f(candidate));
+    let mut order = Vec::new();
+    let mut sets: HashMap<(u64, Vec<u8>), Vec<Candidate>> = HashMap::new();
+    for (mut candidate, result) in candidates.into_iter().zip(results) {
+        match result {
+            Ok(digest) => {
+                let key = (candidate.size, digest.clone());
+                candidate.digest = digest;
+                if !sets.contains_key(&key) {
+                    order.push(key.clone());
+                }
+                sets.entry(key).or_default().push(candidate);
+            }
+            Err(err) => errors.push(Error::from_io(&err, &candidate.path.to_string_lossy())),
+        }
+    }
+    order.into_iter().filter_map(|key| sets.remove(&key)).filter(|set| set.len() > 1).collect()
+}
+
+/// Walks every directory in `roots` and groups the regular files with identical content, using up
+/// to `workers` threads, 0 meaning one per CPU, for the hashing. Fails if a root can't be walked.
+pub fn find_duplicates<P: AsRef<Path>>(roots: &[P], options: &DuplicateOptions, workers: usize) -> Result<DuplicateReport, Error> {
+    let manifest_options = ManifestOptions { follow_symlinks: options.follow_symlinks, skip_hidden: options.skip_hidden, ..ManifestOptions::default() };
+    let mut report = DuplicateReport::default();
+    let mut seen = HashSet::new();
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for root in roots {
+        for path in manifest::find_files(root, &manifest_options)? {
+            let metadata = match fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    report.errors.push(Error::from_io(&err, &path.to_string_lossy()));
+                    continue;
+                }
+            };
+            if !seen.insert(file_id(&metadata, &path)) {
+                continue;
+            }
+            report.files_scanned += 1;
+            if metadata.len() >= options.min_size {
+                by_size.entry(metadata.len()).or_default().push(path);
+            }
+        }
+    }
+
+    // Only files that share a size with another are read at all
+    let candidates = by_size
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .flat_map(|(size, paths)| paths.into_iter().map(move |path| Candidate { path, size, digest: Vec::new() }))
+        .collect();
+    let algorithm = options.algorithm;
+    let fully_hashed = AtomicUsize::new(0);
+    let count_if = |whole: bool, result: io::Result<Vec<u8>>| {
+        if whole && result.is_ok() {
+            fully_hashed.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    };
+    let partial = refine(candidates, workers, &mut report.errors, |c| count_if(c.size <= PARTIAL_LEN * 2, partial_digest(&c.path, c.size, algorithm)));
+
+    let (complete, incomplete): (Vec<_>, Vec<_>) = partial.into_iter().partition(|set| set[0].size <= PARTIAL_LEN * 2);
+    let incomplete: Vec<Candidate> = incomplete.into_iter().flatten().collect();
+    let full = refine(incomplete, workers, &mut report.errors, |c| count_if(true, cache::checksum_file(&c.path, algorithm, false)));
+    report.files_fully_hashed = fully_hashed.into_inner();
+
+    report.groups = complete
+        .into_iter()
+        .chain(full)
+        .map(|set| {
+            let mut paths: Vec<PathBuf> = set.iter().map(|c| c.path.clone()).collect();
+            paths.sort();
+            DuplicateGroup { size: set[0].size, digest: set[0].digest.clone(), paths }
+        })
+        .collect();
+    report.groups.sort_by(|a, b| b.wasted_bytes().cmp(&a.wasted_bytes()).then_with(|| a.paths.cmp(&b.paths)));
+    Ok(report)
+}
+
+/// Options for `get_duplicate_files`, see `DuplicateOptions`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CDuplicateOptions {
+    /// One of the `Algorithm` values.
+    pub algorithm: c_int,
+    pub follow_symlinks: bool,
+    pub skip_hidden: bool,
+    /// Files smaller than this are ignored. 0 includes empty files.
+    pub min_size: u64,
+}
+
+impl CDuplicateOptions {
+    pub fn to_options(self) -> Result<DuplicateOptions, Error> {
+        Ok(DuplicateOptions {
+            algorithm: algorithm_from_c(self.algorithm)?,
+            follow_symlinks: self.follow_symlinks,
+            skip_hidden: self.skip_hidden,
+            min_size: self.min_size,
+        })
+    }
+}
+
+/// One set of identical files in a `CDuplicateReport`.
+#[repr(C)]
+pub struct CDuplicateGroup {
+    pub size: u64,
+    pub wasted_bytes: u64,
+    /// Lowercase hex digest.
+    pub digest: *mut c_char,
+    pub paths: *mut *mut c_char,
+    pub path_count: usize,
+}
+
+/// The result of `get_duplicate_files`, released with `release_duplicate_report`.
+#[repr(C)]
+pub struct CDuplicateReport {
+    pub groups: *mut CDuplicateGroup,
+    pub count: usize,
+    pub wasted_bytes: u64,
+    pub files_scanned: usize,
+    pub files_fully_hashed: usize,
+    /// Files left out because they couldn't be read.
+    pub error_count: usize,
+}
+
+fn to_c_duplicate_report(report: DuplicateReport) -> *mut CDuplicateReport {
+    let wasted_bytes = report.wasted_bytes();
+    let groups = report
+        .groups
+        .into_iter()
+        .map(|group| {
+            let wasted_bytes = group.wasted_bytes();
+            let paths = group.paths.iter().map(|path| bytes_into_c_string(manifest::os_str_bytes(path.as_os_str()))).collect();
+            let (paths, path_count) = into_c_array(paths);
+            CDuplicateGroup { size: group.size, wasted_bytes, digest: bytes_into_c_string(checksum::to_hex(&group.digest).into_bytes()), paths, path_count }
+        })
+        .collect();
+    let (groups, count) = into_c_array(groups);
+    Box::into_raw(Box::new(CDuplicateReport {
+        groups,
+        count,
+        wasted_bytes,
+        files_scanned: report.files_scanned,
+        files_fully_hashed: report.files_fully_hashed,
+        error_count: report.errors.len(),
+    }))
+}
+
+/// Walks the `count` directories in `roots` and groups the files in them with identical content,
+/// biggest waste of space first. A NULL `options` compares every non-empty file with SHA-256.
+/// Files that can't be read are left out and counted in `error_count`, but a root that can't be
+/// walked fails the call with NULL. The result must be freed with `release_duplicate_report`.
+///
+/// # Safety
+///
+/// `roots` must point to `count` valid NUL terminated strings and `options` must be NULL or point
+/// to a valid `CDuplicateOptions`.
+#[no_mangle]
+pub unsafe extern "C" fn get_duplicate_files(roots: *const *const c_char, count: usize, options: *const CDuplicateOptions) -> *mut CDuplicateReport {
+    error::guard(ptr::null_mut(), || {
+        let options = if options.is_null() { DuplicateOptions::default() } else { (*options).to_options()? };
+        if count == 0 {
+            return Ok(to_c_duplicate_report(DuplicateReport::default()));
+        }
+        if roots.is_null() {
+            return Err(Error::new(ErrorCode::NullArgument, "roots is NULL"));
+        }
+        let roots = slice::from_raw_parts(roots, count).iter().map(|p| path_from_c(*p)).collect::<Result<Vec<_>, _>>()?;
+        find_duplicates(&roots, &options, 0).map(to_c_duplicate_report)
+    })
+}
+
+/// Frees a report returned by `get_duplicate_files`.
+///
+/// # Safety
+///
+/// `report` must be NULL or a pointer returned by `get_duplicate_files` that has not been released
+/// yet.
+#[no_mangle]
+pub unsafe extern "C" fn release_duplicate_report(report: *mut CDuplicateReport) {
+    if report.is_null() {
+        return;
+    }
+    error::guard_silent((), || {
+        let report = Box::from_raw(report);
+        for group in from_c_array(report.groups, report.count).iter() {
+            drop(CString::from_raw(group.digest));
+            for path in from_c_array(group.paths, group.path_count).iter() {
+                drop(CString::from_raw(*path));
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::ffi::CStr;
+
+    use test_util::make_tree;
+
+    static BIG: [u8; 100_000] = [7; 100_000];
+    /// Same size, head and tail as `BIG`, so only a full digest tells them apart.
+    static BIG_CHANGED: [u8; 100_000] = {
+        let mut data = [7; 100_000];
+        data[50_000] = 8;
+        data
+    };
+    static COPIES: [(&str, &[u8]); 10] = [
+        ("a/big", &BIG),
+        ("b/big copy", &BIG),
+        ("b/c/big", &BIG),
+        ("a/big changed", &BIG_CHANGED),
+        ("a/small", b"small file"),
+        ("b/small", b"small file"),
+        ("b/other", b"other file"),
+        ("a/unique", b"no other file is this long"),
+        ("a/empty", b""),
+        ("b/empty", b""),
+    ];
+
+    #[test]
+    fn groups_identical_files() {
+        let dir = make_tree(temp_dir().join("file_checksum_duplicates"), &COPIES);
+        let report = find_duplicates(&[dir.join("a"), dir.join("b")], &DuplicateOptions::default(), 2).unwrap();
+        assert!(report.errors.is_empty());
+        assert_eq!(report.files_scanned, 10);
+        // The three copies of `big` and the changed one get past the partial digest, and the
+        // files of 10 bytes are read whole while taking it
+        assert_eq!(report.files_fully_hashed, 7);
+        assert_eq!(report.groups.len(), 2);
+
+        let big = &report.groups[0];
+        assert_eq!(big.paths, vec![dir.join("a/big"), dir.join("b/big copy"), dir.join("b/c/big")]);
+        assert_eq!(big.size, 100_000);
+        assert_eq!(big.wasted_bytes(), 200_000);
+        assert_eq!(big.digest, checksum::checksum_file(dir.join("a/big"), Algorithm::Sha256).unwrap());
+
+        let small = &report.groups[1];
+        assert_eq!(small.paths, vec![dir.join("a/small"), dir.join("b/small")]);
+        assert_eq!(small.digest, checksum::checksum_bytes(b"small file", Algorithm::Sha256));
+        assert_eq!(report.wasted_bytes(), 200_010);
+
+        let options = DuplicateOptions { min_size: 0, algorithm: Algorithm::Md5, ..DuplicateOptions::default() };
+        let report = find_duplicates(&[dir.join("a"), dir.join("b")], &options, 1).unwrap();
+        assert_eq!(report.groups.len(), 3);
+        assert_eq!(report.groups[2].paths, vec![dir.join("a/empty"), dir.join("b/empty")]);
+        assert_eq!(report.groups[0].digest, checksum::checksum_file(dir.join("a/big"), Algorithm::Md5).unwrap());
+    }
+
+    #[test]
+    fn same_file_is_not_its_own_duplicate() {
+        let dir = make_tree(temp_dir().join("file_checksum_duplicates_overlap"), &COPIES);
+        // The whole tree and a directory inside it
+        let report = find_duplicates(&[dir.clone(), dir.join("b")], &DuplicateOptions::default(), 1).unwrap();
+        assert_eq!(report.files_scanned, 10);
+        assert_eq!(report.groups[0].paths.len(), 3);
+
+        #[cfg(unix)]
+        {
+            fs::hard_link(dir.join("a/unique"), dir.join("b/link")).unwrap();
+            let report = find_duplicates(&[&dir], &DuplicateOptions::default(), 1).unwrap();
+            assert!(report.groups.iter().all(|group| !group.paths.iter().any(|path| path.ends_with("unique") || path.ends_with("link"))));
+        }
+
+        assert_eq!(find_duplicates(&[dir.join("missing")], &DuplicateOptions::default(), 1).unwrap_err().code, ErrorCode::NotFound);
+    }
+
+    #[test]
+    fn c_api() {
+        let dir = make_tree(temp_dir().join("file_checksum_duplicates_c"), &COPIES);
+        let a = CString::new(dir.join("a").to_str().unwrap()).unwrap();
+        let b = CString::new(dir.join("b").to_str().unwrap()).unwrap();
+        let roots = [a.as_ptr(), b.as_ptr()];
+        unsafe {
+            let report = get_duplicate_files(roots.as_ptr(), 2, ptr::null());
+            assert!(!report.is_null());
+            assert_eq!(((*report).count, (*report).wasted_bytes, (*report).files_scanned, (*report).error_count), (2, 200_010, 10, 0));
+            let groups = slice::from_raw_parts((*report).groups, (*report).count);
+            assert_eq!((groups[1].size, groups[1].wasted_bytes, groups[1].path_count), (10, 10, 2));
+            let paths = slice::from_raw_parts(groups[1].paths, groups[1].path_count);
+            assert_eq!(CStr::from_ptr(paths[1]).to_str().unwrap(), dir.join("b/small").to_str().unwrap());
+            assert_eq!(CStr::from_ptr(groups[1].digest).to_str().unwrap(), checksum::to_hex(&checksum::checksum_bytes(b"small file", Algorithm::Sha256)));
+            release_duplicate_report(report);
+
+            let options = CDuplicateOptions { algorithm: 99, follow_symlinks: false, skip_hidden: false, min_size: 0 };
+            assert!(get_duplicate_files(roots.as_ptr(), 2, &options).is_null());
+            assert_eq!(error::last_error_code(), ErrorCode::UnsupportedAlgorithm);
+            assert!(get_duplicate_files(ptr::null(), 1, ptr::null()).is_null());
+            assert_eq!(error::last_error_code(), ErrorCode::NullArgument);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Text and binary forms a digest can be returned in. Everything but `Raw` is printable ASCII, so
+//! it can be handed to C as a NUL terminated string.
+
+use checksum;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE64_URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// How a digest is encoded. The values are part of the C ABI and must not change.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    /// Lowercase hex, as printed by `sha256sum`.
+    #[default]
+    HexLower = 0,
+    /// Uppercase hex.
+    HexUpper = 1,
+    /// RFC 4648 base64 with padding, as used by Subresource Integrity and `Content-MD5`.
+    Base64 = 2,
+    /// RFC 4648 URL and file name safe base64, without padding.
+    Base64Url = 3,
+    /// RFC 4648 base32 with padding.
+    Base32 = 4,
+    /// The digest bytes themselves.
+    Raw = 5,
+}
+
+impl Encoding {
+    /// cbindgen:ignore
+    pub const ALL: [Encoding; 6] = [
+        Encoding::HexLower,
+        Encoding::HexUpper,
+        Encoding::Base64,
+        Encoding::Base64Url,
+        Encoding::Base32,
+        Encoding::Raw,
+    ];
+
+    /// Maps the integer passed across the C ABI back to an encoding.
+    pub fn from_raw(value: i32) -> Option<Encoding> {
+        Encoding::ALL.iter().find(|e| **e as i32 == value).cloned()
+    }
+
+    /// Whether the encoded form is text that can be NUL terminated.
+    pub fn is_text(self) -> bool {
+        self != Encoding::Raw
+    }
+}
+
+fn encode_base(digest: &[u8], alphabet: &[u8], bits: usize, pad_to: Option<usize>) -> Vec<u8> {
+    let mut result = Vec::with_capacity((digest.len() * 8).div_ceil(bits) + 8);
+    let mask = (1u32 << bits) - 1;
+    let (mut buffer, mut buffered) = (0u32, 0);
+    for byte in digest {
+        buffer = (buffer << 8) | u32::from(*byte);
+        buffered += 8;
+        while buffered >= bits {
+            buffered -= bits;
+            result.push(alphabet[((buffer >> buffered) & mask) as usize]);
+        }
+    }
+    if buffered > 0 {
+        result.push(alphabet[((buffer << (bits - buffered)) & mask) as usize]);
+    }
+    if let Some(block) = pad_to {
+        while !result.len().is_multiple_of(block) {
+            result.push(b'=');
+        }
+    }
+    result
+}
+
+/// Encodes `digest` as `encoding`.
+pub fn encode(digest: &[u8], encoding: Encoding) -> Vec<u8> {
+    match encoding {
+        Encoding::HexLower => checksum::to_hex(digest).into_bytes(),
+        Encoding::HexUpper => checksum::to_hex(digest).to_ascii_uppercase().into_bytes(),
+        Encoding::Base64 => encode_base(digest, BASE64, 6, Some(4)),
+        Encoding::Base64Url => encode_base(digest, BASE64_URL, 6, None),
+        Encoding::Base32 => encode_base(digest, BASE32, 5, Some(8)),
+        Encoding::Raw => digest.to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use checksum::{checksum_bytes, Algorithm};
+
+    fn encode_str(data: &[u8], encoding: Encoding) -> String {
+        String::from_utf8(encode(data, encoding)).unwrap()
+    }
+
+    #[test]
+    fn rfc4648_vectors() {
+        let inputs: [&[u8]; 7] = [b"", b"f", b"fo", b"foo", b"foob", b"fooba", b"foobar"];
+        let base64 = ["", "Zg==", "Zm8=", "Zm9v", "Zm9vYg==", "Zm9vYmE=", "Zm9vYmFy"];
+        let base32 = ["", "MY======", "MZXQ====", "MZXW6===", "MZXW6YQ=", "MZXW6YTB", "MZXW6YTBOI======"];
+        let hex = ["", "66", "666f", "666f6f", "666f6f62", "666f6f6261", "666f6f626172"];
+        for (i, input) in inputs.iter().enumerate() {
+            assert_eq!(encode_str(input, Encoding::Base64), base64[i]);
+            assert_eq!(encode_str(input, Encoding::Base64Url), base64[i].trim_end_matches('='));
+            assert_eq!(encode_str(input, Encoding::Base32), base32[i]);
+            assert_eq!(encode_str(input, Encoding::HexLower), hex[i]);
+            assert_eq!(encode_str(input, Encoding::HexUpper), hex[i].to_uppercase());
+            assert_eq!(encode(input, Encoding::Raw), *input);
+        }
+    }
+
+    #[test]
+    fn url_safe_alphabet() {
+        assert_eq!(encode_str(&[0xfb, 0xff], Encoding::Base64), "+/8=");
+        assert_eq!(encode_str(&[0xfb, 0xff], Encoding::Base64Url), "-_8");
+    }
+
+    #[test]
+    fn digest_encodings() {
+        // Content-MD5 of an empty body and the SRI hash of "alert('Hello, world.');"
+        assert_eq!(encode_str(&checksum_bytes(b"", Algorithm::Md5), Encoding::Base64), "1B2M2Y8AsgTpgAmY7PhCfg==");
+        assert_eq!(
+            encode_str(&checksum_bytes(b"alert('Hello, world.');", Algorithm::Sha512), Encoding::Base64),
+            "Q2bFTOhEALkN8hOms2FKTDLy7eugP2zFZ1T8LCvX42Fp3WoNr3bjZSAHeOsHrbV1Fu9/A0EzCinRE7Af1ofPrw=="
+        );
+    }
+
+    #[test]
+    fn from_raw() {
+        for encoding in Encoding::ALL.iter() {
+            assert_eq!(Encoding::from_raw(*encoding as i32), Some(*encoding));
+        }
+        assert_eq!(Encoding::from_raw(6), None);
+        assert_eq!(Encoding::from_raw(-1), None);
+    }
+}
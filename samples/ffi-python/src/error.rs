@@ -0,0 +1,224 @@
+//! Error codes reported across the C ABI and the per-thread record of the last failure, in the
+//! manner of `errno` or `GetLastError()`.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::fmt;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+
+use logging::{self, LogLevel};
+
+/// Numeric error codes returned by `file_checksum_last_error_code`. The values are part of the C
+/// ABI and must not change.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    Ok = 0,
+    NullArgument = 1,
+    NotFound = 2,
+    PermissionDenied = 3,
+    InvalidUtf8 = 4,
+    Io = 5,
+    UnsupportedAlgorithm = 6,
+    OutOfMemory = 7,
+    InvalidHandle = 8,
+    HandleFinalized = 9,
+    BufferTooSmall = 10,
+    InvalidManifest = 11,
+    InvalidArgument = 12,
+    /// The library panicked. The call was abandoned but the process can carry on.
+    Panic = 13,
+    /// A progress callback asked for the operation to stop.
+    Cancelled = 14,
+    /// A signature or delta is damaged, or a delta was applied to the wrong file.
+    InvalidDelta = 15,
+    /// An archive is damaged, truncated or uses a feature that isn't supported.
+    InvalidArchive = 16,
+}
+
+/// A failure inside the library, carrying the code for C callers and a readable message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl Error {
+    pub fn new<S: Into<String>>(code: ErrorCode, message: S) -> Error {
+        Error { code, message: message.into() }
+    }
+
+    /// Converts an I/O error, prefixing the message with the path it happened on.
+    pub fn from_io(err: &io::Error, path: &str) -> Error {
+        Error::new(io_error_code(err), format!("{}: {}", path, err))
+    }
+}
+
+/// The code an I/O error is reported with.
+pub fn io_error_code(err: &io::Error) -> ErrorCode {
+    match err.kind() {
+        io::ErrorKind::NotFound => ErrorCode::NotFound,
+        io::ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+        _ => ErrorCode::Io,
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::new(io_error_code(&err), err.to_string())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+struct LastError {
+    code: ErrorCode,
+    message: Option<CString>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<LastError> = const { RefCell::new(LastError { code: ErrorCode::Ok, message: None }) };
+}
+
+/// Records `err` as the calling thread's last error.
+pub fn set_last_error(err: Error) {
+    // Interior NULs can't be represented in a C string so they are dropped from the message
+    let message = CString::new(err.message.replace('\0', "")).ok();
+    LAST_ERROR.with(|last| *last.borrow_mut() = LastError { code: err.code, message });
+}
+
+/// Resets the calling thread's last error to `ErrorCode::Ok`.
+pub fn clear_last_error() {
+    LAST_ERROR.with(|last| *last.borrow_mut() = LastError { code: ErrorCode::Ok, message: None });
+}
+
+pub fn last_error_code() -> ErrorCode {
+    LAST_ERROR.with(|last| last.borrow().code)
+}
+
+/// Calls `f` with the calling thread's last error message, if there is one.
+pub fn with_last_error_message<T, F: FnOnce(Option<&CString>) -> T>(f: F) -> T {
+    LAST_ERROR.with(|last| f(last.borrow().message.as_ref()))
+}
+
+/// Records the outcome of an exported call, returning the value on success or `failed` after
+/// storing the error.
+fn report<T>(result: Result<T, Error>, failed: T) -> T {
+    match result {
+        Ok(value) => {
+            clear_last_error();
+            value
+        }
+        Err(err) => {
+            set_failure(err);
+            failed
+        }
+    }
+}
+
+/// Stores the error of an exported call as the last error, logging it first since the log
+/// callback may itself call into the library.
+fn set_failure(err: Error) {
+    logging::log(LogLevel::Error, "error", format_args!("{}", err));
+    set_last_error(err);
+}
+
+fn panic_error(payload: Box<dyn Any + Send>) -> Error {
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    };
+    Error::new(ErrorCode::Panic, format!("panic in file_checksum: {}", message))
+}
+
+/// Runs the body of an exported function. Unwinding across the C ABI is undefined behaviour, so
+/// a panic is caught and recorded as an `ErrorCode::Panic` error. Returns the value on success or
+/// `failed` after storing the error.
+pub fn guard<T, F: FnOnce() -> Result<T, Error>>(failed: T, f: F) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => report(result, failed),
+        Err(payload) => {
+            set_failure(panic_error(payload));
+            failed
+        }
+    }
+}
+
+/// Like `guard` for exported functions that return a status, returning the `ErrorCode`.
+pub fn guard_status<F: FnOnce() -> Result<(), Error>>(f: F) -> ErrorCode {
+    guard((), f);
+    last_error_code()
+}
+
+/// Like `guard` for exported functions that can't fail, such as the release functions. The last
+/// error is left alone unless there is a panic.
+pub fn guard_silent<T, F: FnOnce() -> T>(failed: T, f: F) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => value,
+        Err(payload) => {
+            set_failure(panic_error(payload));
+            failed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn io_error_codes() {
+        let not_found = io::Error::new(io::ErrorKind::NotFound, "gone");
+        assert_eq!(Error::from_io(&not_found, "/a").code, ErrorCode::NotFound);
+        assert_eq!(Error::from_io(&not_found, "/a").message, "/a: gone");
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "no");
+        assert_eq!(Error::from(denied).code, ErrorCode::PermissionDenied);
+        let other = io::Error::other("bad sector");
+        assert_eq!(Error::from(other).code, ErrorCode::Io);
+    }
+
+    #[test]
+    fn last_error_is_per_thread() {
+        set_last_error(Error::new(ErrorCode::NotFound, "missing"));
+        thread::spawn(|| assert_eq!(last_error_code(), ErrorCode::Ok)).join().unwrap();
+        assert_eq!(last_error_code(), ErrorCode::NotFound);
+        with_last_error_message(|m| assert_eq!(m.unwrap().to_str().unwrap(), "missing"));
+
+        assert_eq!(guard(0, || Ok(1)), 1);
+        assert_eq!(last_error_code(), ErrorCode::Ok);
+        with_last_error_message(|m| assert!(m.is_none()));
+    }
+
+    #[test]
+    fn guards_catch_panics() {
+        let result: i32 = guard(-1, || panic!("boom"));
+        assert_eq!(result, -1);
+        assert_eq!(last_error_code(), ErrorCode::Panic);
+        with_last_error_message(|m| assert_eq!(m.unwrap().to_str().unwrap(), "panic in file_checksum: boom"));
+
+        assert_eq!(guard_status(|| Err(Error::new(ErrorCode::Io, "io"))), ErrorCode::Io);
+        assert_eq!(guard_status(|| Ok(())), ErrorCode::Ok);
+        let values: Vec<u8> = Vec::new();
+        assert_eq!(guard_status(|| if values[3] == 0 { Ok(()) } else { Err(Error::new(ErrorCode::Io, "")) }), ErrorCode::Panic);
+        with_last_error_message(|m| assert!(m.unwrap().to_str().unwrap().contains("index out of bounds")));
+
+        // Only a panic disturbs the last error
+        set_last_error(Error::new(ErrorCode::NotFound, "missing"));
+        assert_eq!(guard_silent(0, || 1), 1);
+        assert_eq!(last_error_code(), ErrorCode::NotFound);
+        assert_eq!(guard_silent(0, || panic!("{} went wrong", "release")), 0);
+        with_last_error_message(|m| assert_eq!(m.unwrap().to_str().unwrap(), "panic in file_checksum: release went wrong"));
+    }
+}
@@ -0,0 +1,139 @@
+//! Conversions shared by the exported functions for arguments and results crossing the C ABI.
+
+use std::ffi::{CStr, CString};
+use std::path::Path;
+use std::ptr;
+use std::slice;
+use libc::{c_char, c_int, malloc};
+
+use checksum::Algorithm;
+use encoding::{self, Encoding};
+use error::{Error, ErrorCode};
+
+/// Copies `value` into a NUL terminated string allocated with `malloc` so the caller can hand it
+/// back to `release_checksum`.
+pub fn malloc_c_string(value: &str) -> Result<*mut c_char, Error> {
+    malloc_c_bytes(value.as_bytes())
+}
+
+/// Like `malloc_c_string` for text that may not be UTF-8, such as file names.
+pub fn malloc_c_bytes(value: &[u8]) -> Result<*mut c_char, Error> {
+    unsafe {
+        let result = malloc(value.len() + 1) as *mut c_char;
+        if result.is_null() {
+            return Err(Error::new(ErrorCode::OutOfMemory, "out of memory"));
+        }
+        ptr::copy_nonoverlapping(value.as_ptr() as *const c_char, result, value.len());
+        *result.add(value.len()) = 0;
+        Ok(result)
+    }
+}
+
+/// Borrows the NUL terminated path passed in by the caller. On Unix a path is any sequence of
+/// bytes, so names in legacy encodings such as Latin-1 are used as they are. Elsewhere paths must
+/// be UTF-8 and anything else fails with `InvalidUtf8`.
+pub unsafe fn path_from_c<'a>(filepath: *const c_char) -> Result<&'a Path, Error> {
+    if filepath.is_null() {
+        return Err(Error::new(ErrorCode::NullArgument, "filepath is NULL"));
+    }
+    path_from_bytes(CStr::from_ptr(filepath).to_bytes())
+}
+
+/// Borrows a path of `len` bytes that needn't be NUL terminated, see `path_from_c`. Paths can't
+/// contain NUL so one anywhere in the bytes fails with `InvalidArgument`.
+pub unsafe fn path_from_c_bytes<'a>(filepath: *const u8, len: usize) -> Result<&'a Path, Error> {
+    if filepath.is_null() {
+        return Err(Error::new(ErrorCode::NullArgument, "filepath is NULL"));
+    }
+    let bytes = slice::from_raw_parts(filepath, len);
+    if bytes.contains(&0) {
+        return Err(Error::new(ErrorCode::InvalidArgument, "filepath contains a NUL byte"));
+    }
+    path_from_bytes(bytes)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> Result<&Path, Error> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    Ok(Path::new(OsStr::from_bytes(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> Result<&Path, Error> {
+    ::std::str::from_utf8(bytes)
+        .map(Path::new)
+        .map_err(|err| Error::new(ErrorCode::InvalidUtf8, format!("filepath is not valid UTF-8: {}", err)))
+}
+
+pub fn algorithm_from_c(algorithm: c_int) -> Result<Algorithm, Error> {
+    Algorithm::from_raw(algorithm)
+        .ok_or_else(|| Error::new(ErrorCode::UnsupportedAlgorithm, format!("unsupported algorithm {}", algorithm)))
+}
+
+pub fn encoding_from_c(encoding: c_int) -> Result<Encoding, Error> {
+    Encoding::from_raw(encoding)
+        .ok_or_else(|| Error::new(ErrorCode::InvalidArgument, format!("unsupported encoding {}", encoding)))
+}
+
+/// Encodes `digest` into a `malloc`ed buffer for `release_checksum`, storing its length without
+/// the NUL terminator in `*out_len`. The length is optional for text but required for `Raw`, which
+/// may contain NUL bytes.
+pub unsafe fn malloc_encoded(digest: &[u8], encoding: Encoding, out_len: *mut usize) -> Result<*mut c_char, Error> {
+    if out_len.is_null() && !encoding.is_text() {
+        return Err(Error::new(ErrorCode::NullArgument, "out_len is NULL but raw output needs a length"));
+    }
+    let encoded = encoding::encode(digest, encoding);
+    let result = malloc_c_bytes(&encoded)?;
+    if !out_len.is_null() {
+        *out_len = encoded.len();
+    }
+    Ok(result)
+}
+
+/// Checks the caller's buffer can hold a string of `len` bytes and its NUL terminator. `*written`
+/// is set to `len` whether or not it fits, so a caller can retry with a big enough buffer.
+pub unsafe fn check_c_buffer(len: usize, out_buf: *mut c_char, out_len: usize, written: *mut usize) -> Result<(), Error> {
+    if !written.is_null() {
+        *written = len;
+    }
+    if out_len <= len {
+        if !out_buf.is_null() && out_len > 0 {
+            *out_buf = 0;
+        }
+        return Err(Error::new(
+            ErrorCode::BufferTooSmall,
+            format!("buffer of {} bytes is too small, {} are required", out_len, len + 1),
+        ));
+    }
+    if out_buf.is_null() {
+        return Err(Error::new(ErrorCode::NullArgument, "out_buf is NULL"));
+    }
+    Ok(())
+}
+
+/// Copies `value` and a NUL terminator into the caller's buffer, see `check_c_buffer`.
+pub unsafe fn copy_to_c_buffer(value: &str, out_buf: *mut c_char, out_len: usize, written: *mut usize) -> Result<(), Error> {
+    check_c_buffer(value.len(), out_buf, out_len, written)?;
+    ptr::copy_nonoverlapping(value.as_ptr() as *const c_char, out_buf, value.len());
+    *out_buf.add(value.len()) = 0;
+    Ok(())
+}
+
+/// Hands a vector to C as a pointer and length, to be taken back with `from_c_array`.
+pub fn into_c_array<T>(items: Vec<T>) -> (*mut T, usize) {
+    let items = items.into_boxed_slice();
+    let count = items.len();
+    (Box::into_raw(items) as *mut T, count)
+}
+
+/// Takes back ownership of an array made by `into_c_array`.
+pub unsafe fn from_c_array<T>(items: *mut T, count: usize) -> Box<[T]> {
+    Box::from_raw(ptr::slice_from_raw_parts_mut(items, count))
+}
+
+/// Turns bytes known to have no interior NULs, e.g. a file path, into a string owned by C until it
+/// is handed back to `CString::from_raw`.
+pub fn bytes_into_c_string(bytes: Vec<u8>) -> *mut c_char {
+    CString::new(bytes).expect("unexpected NUL in string").into_raw()
+}
@@ -0,0 +1,139 @@
+//! Opaque handles for incremental hashing. A handle is an id, never an address, mapped to its
+//! state in a registry of live handles. Ids are never reused, so a stale or foreign pointer is
+//! rejected with an error rather than dereferenced or mistaken for a newer handle.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use checksum::{Algorithm, Hasher};
+use error::{Error, ErrorCode};
+
+/// Opaque state of an incremental digest, see `checksum_new`. The hasher is taken out when the
+/// handle is finalized, after which only `checksum_free` is valid.
+pub struct ChecksumHandle {
+    hasher: Option<Hasher>,
+}
+
+struct Registry {
+    /// The id the next handle gets. 0 is never used, it would be NULL.
+    next_id: usize,
+    live: BTreeMap<usize, Box<ChecksumHandle>>,
+}
+
+static LIVE_HANDLES: Mutex<Registry> = Mutex::new(Registry { next_id: 1, live: BTreeMap::new() });
+
+fn with_registry<T, F: FnOnce(&mut Registry) -> T>(f: F) -> T {
+    // The registry is always left consistent so a poisoned lock is still usable
+    f(&mut LIVE_HANDLES.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+}
+
+/// Allocates a handle for `algorithm` and registers it as live under a fresh id.
+pub fn new_handle(algorithm: Algorithm) -> *mut ChecksumHandle {
+    with_registry(|registry| {
+        let id = registry.next_id;
+        registry.next_id += 1;
+        registry.live.insert(id, Box::new(ChecksumHandle { hasher: Some(Hasher::new(algorithm)) }));
+        id as *mut ChecksumHandle
+    })
+}
+
+/// Looks up the state behind a live handle. The state is boxed, so it stays put while other
+/// handles come and go.
+fn live_state(handle: *mut ChecksumHandle) -> Result<*mut ChecksumHandle, Error> {
+    if handle.is_null() {
+        return Err(Error::new(ErrorCode::NullArgument, "handle is NULL"));
+    }
+    with_registry(|registry| registry.live.get_mut(&(handle as usize)).map(|state| &mut **state as *mut ChecksumHandle))
+        .ok_or_else(|| Error::new(ErrorCode::InvalidHandle, "handle is not live, it was freed or never allocated"))
+}
+
+/// Borrows the hasher of a live, unfinalized handle.
+///
+/// # Safety
+///
+/// The handle must not be used from another thread at the same time.
+pub unsafe fn hasher_mut<'a>(handle: *mut ChecksumHandle) -> Result<&'a mut Hasher, Error> {
+    (*live_state(handle)?)
+        .hasher
+        .as_mut()
+        .ok_or_else(|| Error::new(ErrorCode::HandleFinalized, "handle has already been finalized"))
+}
+
+/// Finishes the digest of a live handle. The handle stays live until it is freed.
+///
+/// # Safety
+///
+/// The handle must not be used from another thread at the same time.
+pub unsafe fn finalize_handle(handle: *mut ChecksumHandle) -> Result<Vec<u8>, Error> {
+    (*live_state(handle)?)
+        .hasher
+        .take()
+        .map(Hasher::finalize)
+        .ok_or_else(|| Error::new(ErrorCode::HandleFinalized, "handle has already been finalized"))
+}
+
+/// Unregisters and drops a live handle.
+///
+/// # Safety
+///
+/// The handle must not be used from another thread at the same time.
+pub unsafe fn free_handle(handle: *mut ChecksumHandle) -> Result<(), Error> {
+    if handle.is_null() {
+        return Err(Error::new(ErrorCode::NullArgument, "handle is NULL"));
+    }
+    match with_registry(|registry| registry.live.remove(&(handle as usize))) {
+        Some(_) => Ok(()),
+        None => Err(Error::new(ErrorCode::InvalidHandle, "handle is not live, it was freed or never allocated")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use checksum::to_hex;
+    use std::ptr;
+
+    #[test]
+    fn update_and_finalize() {
+        let handle = new_handle(Algorithm::Sha1);
+        unsafe {
+            hasher_mut(handle).unwrap().update(b"a");
+            hasher_mut(handle).unwrap().update(b"bc");
+            let digest = finalize_handle(handle).unwrap();
+            assert_eq!(to_hex(&digest), "a9993e364706816aba3e25717850c26c9cd0d89d");
+
+            assert_eq!(hasher_mut(handle).err().unwrap().code, ErrorCode::HandleFinalized);
+            assert_eq!(finalize_handle(handle).unwrap_err().code, ErrorCode::HandleFinalized);
+            free_handle(handle).unwrap();
+        }
+    }
+
+    #[test]
+    fn stale_handles() {
+        let handle = new_handle(Algorithm::Md5);
+        unsafe {
+            free_handle(handle).unwrap();
+            assert_eq!(free_handle(handle).unwrap_err().code, ErrorCode::InvalidHandle);
+            assert_eq!(hasher_mut(handle).err().unwrap().code, ErrorCode::InvalidHandle);
+            assert_eq!(free_handle(ptr::null_mut()).unwrap_err().code, ErrorCode::NullArgument);
+        }
+    }
+
+    #[test]
+    fn stale_handle_is_not_mistaken_for_a_new_one() {
+        let stale = new_handle(Algorithm::Md5);
+        unsafe {
+            free_handle(stale).unwrap();
+            let fresh = new_handle(Algorithm::Md5);
+            assert_ne!(fresh, stale);
+            assert_eq!(hasher_mut(stale).err().unwrap().code, ErrorCode::InvalidHandle);
+            assert_eq!(finalize_handle(stale).unwrap_err().code, ErrorCode::InvalidHandle);
+            assert_eq!(free_handle(stale).unwrap_err().code, ErrorCode::InvalidHandle);
+
+            // The new handle is untouched by the stale calls
+            hasher_mut(fresh).unwrap().update(b"abc");
+            assert_eq!(to_hex(&finalize_handle(fresh).unwrap()), "900150983cd24fb0d6963f7d28e17f72");
+            free_handle(fresh).unwrap();
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! Keyed digests, HMAC as in RFC 2104, of files. Anyone who changes a file can recompute its plain
+//! digest, but only a holder of the key can produce a matching HMAC, so a list of them can be
+//! used to sign a manifest.
+//!
+//! Only SHA-256 and SHA-512 are offered. Every copy of the key the library makes, the padded key
+//! blocks, the digest of an over-long key and the hash states that have absorbed any of them, is
+//! overwritten with zeros before its memory is freed. The caller's own key buffer is never
+//! modified.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::ptr;
+use std::slice;
+use libc::{c_char, c_int};
+use sha2::{Digest, Sha256, Sha512};
+use zeroize::{self, Zeroizing};
+
+use checksum::{Algorithm, CHUNK_SIZE};
+use encoding::Encoding;
+use error::{self, Error, ErrorCode};
+use ffi::{algorithm_from_c, encoding_from_c, malloc_encoded, path_from_c};
+
+const IPAD: u8 = 0x36;
+const OPAD: u8 = 0x5c;
+
+/// The block size of `algorithm`'s compression function, or an error if HMAC isn't offered for it.
+fn block_size(algorithm: Algorithm) -> Result<usize, Error> {
+    match algorithm {
+        Algorithm::Sha256 => Ok(64),
+        Algorithm::Sha512 => Ok(128),
+        _ => Err(Error::new(ErrorCode::UnsupportedAlgorithm, format!("HMAC is not supported with {}", algorithm.name()))),
+    }
+}
+
+/// A hash state fed key material. Its midstate is as good as the key for forging MACs, so it is
+/// finalized in place and overwritten with zeros when dropped, unlike `Hasher`.
+enum KeyedHasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl KeyedHasher {
+    fn new(algorithm: Algorithm) -> KeyedHasher {
+        match algorithm {
+            Algorithm::Sha256 => KeyedHasher::Sha256(Sha256::new()),
+            Algorithm::Sha512 => KeyedHasher::Sha512(Sha512::new()),
+            _ => unreachable!("block_size rejects {}", algorithm.name()),
+        }
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        match *self {
+            KeyedHasher::Sha256(_) => Algorithm::Sha256,
+            KeyedHasher::Sha512(_) => Algorithm::Sha512,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match *self {
+            KeyedHasher::Sha256(ref mut h) => Digest::update(h, data),
+            KeyedHasher::Sha512(ref mut h) => Digest::update(h, data),
+        }
+    }
+
+    /// Returns the digest, resetting the state in place rather than moving it out to finalize.
+    fn finalize(&mut self) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(match *self {
+            KeyedHasher::Sha256(ref mut h) => h.finalize_reset().to_vec(),
+            KeyedHasher::Sha512(ref mut h) => h.finalize_reset().to_vec(),
+        })
+    }
+}
+
+impl Drop for KeyedHasher {
+    fn drop(&mut self) {
+        // Both states are plain arrays and counters, for which all zeros is a valid value
+        unsafe {
+            match *self {
+                KeyedHasher::Sha256(ref mut h) => zeroize::zeroize_flat_type(h),
+                KeyedHasher::Sha512(ref mut h) => zeroize::zeroize_flat_type(h),
+            }
+        }
+    }
+}
+
+/// An HMAC being computed incrementally, like `Hasher` for plain digests.
+pub struct Hmac {
+    inner: KeyedHasher,
+    /// The key XORed with the outer pad, kept until `finalize`.
+    outer_key: Zeroizing<Vec<u8>>,
+}
+
+impl Hmac {
+    /// Starts an HMAC with `key`, which may be any length. Fails with `UnsupportedAlgorithm` for
+    /// anything but SHA-256 and SHA-512.
+    pub fn new(algorithm: Algorithm, key: &[u8]) -> Result<Hmac, Error> {
+        let block_size = block_size(algorithm)?;
+        // Keys longer than a block are replaced by their digest, shorter ones are zero padded
+        let mut block = Zeroizing::new(vec![0u8; block_size]);
+        if key.len() > block_size {
+            let mut hasher = KeyedHasher::new(algorithm);
+            hasher.update(key);
+            let digest = hasher.finalize();
+            block[..digest.len()].copy_from_slice(&digest);
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+
+        let inner_key = Zeroizing::new(block.iter().map(|b| b ^ IPAD).collect::<Vec<u8>>());
+        let outer_key = Zeroizing::new(block.iter().map(|b| b ^ OPAD).collect::<Vec<u8>>());
+        let mut inner = KeyedHasher::new(algorithm);
+        inner.update(&inner_key);
+        Ok(Hmac { inner, outer_key })
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.inner.algorithm()
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    /// Returns the HMAC, as long as the underlying digest.
+    pub fn finalize(mut self) -> Vec<u8> {
+        let mut outer = KeyedHasher::new(self.inner.algorithm());
+        outer.update(&self.outer_key);
+        outer.update(&self.inner.finalize());
+        outer.finalize().to_vec()
+    }
+}
+
+/// Returns the HMAC of bytes already in memory.
+pub fn hmac_bytes(data: &[u8], algorithm: Algorithm, key: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut hmac = Hmac::new(algorithm, key)?;
+    hmac.update(data);
+    Ok(hmac.finalize())
+}
+
+/// Feeds everything `reader` yields to `hmac` and returns the result.
+pub fn hmac_reader<R: Read>(reader: &mut R, mut hmac: Hmac) -> io::Result<Vec<u8>> {
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(hmac.finalize()),
+            Ok(read) => hmac.update(&buffer[..read]),
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Returns the HMAC of the file at `path`.
+pub fn hmac_file(path: &Path, algorithm: Algorithm, key: &[u8]) -> Result<Vec<u8>, Error> {
+    let to_error = |err| Error::from_io(&err, &path.to_string_lossy());
+    // Check the algorithm before touching the file so the error doesn't depend on whether it exists
+    let hmac = Hmac::new(algorithm, key)?;
+    let mut file = File::open(path).map_err(to_error)?;
+    hmac_reader(&mut file, hmac).map_err(to_error)
+}
+
+unsafe fn hmac_to_c_string(filepath: *const c_char, algorithm: c_int, key: *const u8, key_len: usize, encoding: Encoding, out_len: *mut usize) -> Result<*mut c_char, Error> {
+    let filepath = path_from_c(filepath)?;
+    let algorithm = algorithm_from_c(algorithm)?;
+    let key = if key_len == 0 {
+        &[][..]
+    } else if key.is_null() {
+        return Err(Error::new(ErrorCode::NullArgument, "key is NULL"));
+    } else {
+        slice::from_raw_parts(key, key_len)
+    };
+    malloc_encoded(&hmac_file(filepath, algorithm, key)?, encoding, out_len)
+}
+
+/// Returns the HMAC of the file at `filepath` keyed with the `key_len` bytes at `key`, as a
+/// lowercase hex string. `algorithm` must be `Sha256` or `Sha512`, anything else fails with
+/// `UnsupportedAlgorithm`. The library's copies of the key are zeroed before returning, the
+/// caller's buffer is left as it is. Returns NULL on failure. The result must be freed with
+/// `release_checksum`.
+///
+/// # Safety
+///
+/// `filepath` must be NULL or point to a valid NUL terminated string and `key` must point to at
+/// least `key_len` readable bytes, or may be NULL if `key_len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn get_checksum_hmac(filepath: *const c_char, algorithm: c_int, key: *const u8, key_len: usize) -> *mut c_char {
+    error::guard(ptr::null_mut(), || hmac_to_c_string(filepath, algorithm, key, key_len, Encoding::HexLower, ptr::null_mut()))
+}
+
+/// Like `get_checksum_hmac` with the result returned as `encoding`, see `get_checksum_encoded`.
+/// The result must be freed with `release_checksum`.
+///
+/// # Safety
+///
+/// As for `get_checksum_hmac`, and `out_len` must be NULL or point to a writable `size_t`.
+#[no_mangle]
+pub unsafe extern "C" fn get_checksum_hmac_encoded(filepath: *const c_char, algorithm: c_int, key: *const u8, key_len: usize, encoding: c_int, out_len: *mut usize) -> *mut c_char {
+    error::guard(ptr::null_mut(), || {
+        let encoding = encoding_from_c(encoding)?;
+        hmac_to_c_string(filepath, algorithm, key, key_len, encoding, out_len)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::ffi::{CStr, CString};
+    use std::fs;
+
+    use checksum::to_hex;
+    use error::last_error_code;
+    use release_checksum;
+
+    const LONG_KEY: [u8; 131] = [0xaa; 131];
+
+    /// RFC 4231 section 4 as (key, data, HMAC-SHA-256, HMAC-SHA-512).
+    fn rfc_4231_cases() -> Vec<(Vec<u8>, Vec<u8>, &'static str, &'static str)> {
+        vec![
+            (
+                vec![0x0b; 20],
+                b"Hi There".to_vec(),
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+                "87aa7cdea5ef619d4ff0b4241a1d6cb02379f4e2ce4ec2787ad0b30545e17cdedaa833b7d6b8a702038b274eaea3f4e4be9d914eeb61f1702e696c203a126854",
+            ),
+            (
+                b"Jefe".to_vec(),
+                b"what do ya want for nothing?".to_vec(),
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+                "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea2505549758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737",
+            ),
+            (
+                vec![0xaa; 20],
+                vec![0xdd; 50],
+                "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe",
+                "fa73b0089d56a284efb0f0756c890be9b1b5dbdd8ee81a3655f83e33b2279d39bf3e848279a722c806b485a47e67c807b946a337bee8942674278859e13292fb",
+            ),
+            (
+                (1..26).collect(),
+                vec![0xcd; 50],
+                "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+                "b0ba465637458c6990e5a8c5f61d4af7e576d97ff94b872de76f8050361ee3dba91ca5c11aa25eb4d679275cc5788063a5f19741120c4f2de2adebeb10a298dd",
+            ),
+            // Test case 5 only gives the first 128 bits of the output
+            (vec![0x0c; 20], b"Test With Truncation".to_vec(), "a3b6167473100ee06e0c796c2955552b", "415fad6271580a531d4179bc891d87a6"),
+            (
+                LONG_KEY.to_vec(),
+                b"Test Using Larger Than Block-Size Key - Hash Key First".to_vec(),
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+                "80b24263c7c1a3ebb71493c1dd7be8b49b46d1f41b4aeec1121b013783f8f3526b56d037e05f2598bd0fd2215d6a1e5295e64f73f63f0aec8b915a985d786598",
+            ),
+            (
+                LONG_KEY.to_vec(),
+                b"This is a test using a larger than block-size key and a larger than block-size data. The key needs to be hashed before being used by the HMAC algorithm.".to_vec(),
+                "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+                "e37b6a775dc87dbaa4dfa9f96e5e3ffddebd71f8867289865df5a32d20cdc944b6022cac3c4982b10d5eeb55c3e4de15134676fb6de0446065c97440fa8c6a58",
+            ),
+        ]
+    }
+
+    #[test]
+    fn rfc_4231_vectors() {
+        for (i, (key, data, sha256, sha512)) in rfc_4231_cases().into_iter().enumerate() {
+            let hmac256 = to_hex(&hmac_bytes(&data, Algorithm::Sha256, &key).unwrap());
+            let hmac512 = to_hex(&hmac_bytes(&data, Algorithm::Sha512, &key).unwrap());
+            assert!(hmac256.starts_with(sha256), "test case {} HMAC-SHA-256", i + 1);
+            assert!(hmac512.starts_with(sha512), "test case {} HMAC-SHA-512", i + 1);
+        }
+    }
+
+    #[test]
+    fn incremental_matches_one_shot() {
+        let data = vec![0x5a; CHUNK_SIZE * 2 + 17];
+        let mut hmac = Hmac::new(Algorithm::Sha512, b"key").unwrap();
+        for piece in data.chunks(1000) {
+            hmac.update(piece);
+        }
+        assert_eq!(hmac.algorithm(), Algorithm::Sha512);
+        assert_eq!(hmac.finalize(), hmac_bytes(&data, Algorithm::Sha512, b"key").unwrap());
+        let hmac = Hmac::new(Algorithm::Sha512, b"key").unwrap();
+        assert_eq!(hmac_reader(&mut &data[..], hmac).unwrap(), hmac_bytes(&data, Algorithm::Sha512, b"key").unwrap());
+    }
+
+    #[test]
+    fn other_algorithms_are_rejected() {
+        for algorithm in Algorithm::ALL.iter().cloned() {
+            let supported = algorithm == Algorithm::Sha256 || algorithm == Algorithm::Sha512;
+            assert_eq!(Hmac::new(algorithm, b"key").is_ok(), supported, "{:?}", algorithm);
+        }
+        let err = hmac_file(Path::new("/does/not/exist"), Algorithm::Md5, b"key").err().unwrap();
+        assert_eq!(err.code, ErrorCode::UnsupportedAlgorithm);
+    }
+
+    #[test]
+    fn hmac_of_file() {
+        let path = temp_dir().join("file_checksum_hmac");
+        fs::write(&path, b"what do ya want for nothing?").unwrap();
+        let filepath = CString::new(path.to_str().unwrap()).unwrap();
+        let key = b"Jefe";
+        unsafe {
+            let result = get_checksum_hmac(filepath.as_ptr(), Algorithm::Sha256 as c_int, key.as_ptr(), key.len());
+            assert_eq!(CStr::from_ptr(result).to_str().unwrap(), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+            release_checksum(result);
+
+            let mut len = 0;
+            let result = get_checksum_hmac_encoded(filepath.as_ptr(), Algorithm::Sha512 as c_int, key.as_ptr(), key.len(), Encoding::Raw as c_int, &mut len);
+            assert_eq!(slice::from_raw_parts(result as *const u8, len), &hmac_file(&path, Algorithm::Sha512, key).unwrap()[..]);
+            release_checksum(result);
+
+            // A different key gives a different result
+            let result = get_checksum_hmac(filepath.as_ptr(), Algorithm::Sha256 as c_int, b"Jeff".as_ptr(), 4);
+            assert_ne!(CStr::from_ptr(result).to_str().unwrap(), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+            release_checksum(result);
+
+            // An empty key is allowed, but a missing non-empty one isn't
+            let result = get_checksum_hmac(filepath.as_ptr(), Algorithm::Sha256 as c_int, ptr::null(), 0);
+            assert!(!result.is_null());
+            release_checksum(result);
+            assert!(get_checksum_hmac(filepath.as_ptr(), Algorithm::Sha256 as c_int, ptr::null(), 4).is_null());
+            assert_eq!(last_error_code(), ErrorCode::NullArgument);
+            assert!(get_checksum_hmac(filepath.as_ptr(), Algorithm::Crc32 as c_int, key.as_ptr(), key.len()).is_null());
+            assert_eq!(last_error_code(), ErrorCode::UnsupportedAlgorithm);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
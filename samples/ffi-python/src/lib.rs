@@ -1,27 +1,994 @@
 extern crate libc;
+extern crate adler2;
+extern crate blake2;
+extern crate crc32fast;
+extern crate flate2;
+extern crate md5;
+extern crate memmap2;
+extern crate sha1;
+extern crate sha2;
+extern crate tar;
+extern crate zeroize;
+extern crate zip;
+
+pub mod archive;
+pub mod batch;
+pub mod cache;
+pub mod checksum;
+pub mod chunking;
+pub mod delta;
+pub mod duplicates;
+pub mod encoding;
+pub mod error;
+pub mod handle;
+pub mod hmac;
+pub mod logging;
+pub mod manifest;
+pub mod sumfile;
+mod ffi;
+#[cfg(test)]
+mod test_util;
 
 use std::ffi::CString;
+use std::fs::{self, File};
+use std::mem::ManuallyDrop;
+use std::path::Path;
 use std::ptr;
-use libc::{c_char, c_void, malloc, memset, strcpy, free};
+use std::slice;
+use std::time::{SystemTime, UNIX_EPOCH};
+use libc::{c_char, c_int, c_void, free};
+
+use checksum::{Algorithm, MAX_DIGEST_LEN};
+use encoding::Encoding;
+use error::{Error, ErrorCode};
+use ffi::{algorithm_from_c, check_c_buffer, copy_to_c_buffer, encoding_from_c, malloc_c_string, malloc_encoded, path_from_c, path_from_c_bytes};
+use handle::ChecksumHandle;
+
+/// Describes one of the supported algorithms, see `get_supported_algorithms`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct AlgorithmInfo {
+    /// The value to pass as the `algorithm` argument of the exported functions.
+    pub algorithm: c_int,
+    /// Static NUL terminated name of the algorithm, e.g. "SHA256". Must not be freed.
+    pub name: *const c_char,
+    /// Length of the digest in bytes. The hex string is twice as long.
+    pub digest_len: usize,
+}
+
+fn algorithm_c_name(algorithm: Algorithm) -> &'static [u8] {
+    match algorithm {
+        Algorithm::Crc32 => b"CRC32\0",
+        Algorithm::Adler32 => b"ADLER32\0",
+        Algorithm::Md5 => b"MD5\0",
+        Algorithm::Sha1 => b"SHA1\0",
+        Algorithm::Sha256 => b"SHA256\0",
+        Algorithm::Sha512 => b"SHA512\0",
+        Algorithm::Blake2b => b"BLAKE2b\0",
+    }
+}
+
+unsafe fn checksum_to_c_string(filepath: *const c_char, algorithm: Algorithm, encoding: Encoding, out_len: *mut usize) -> Result<*mut c_char, Error> {
+    checksum_path_to_c_string(path_from_c(filepath)?, algorithm, encoding, out_len)
+}
+
+unsafe fn checksum_path_to_c_string(filepath: &Path, algorithm: Algorithm, encoding: Encoding, out_len: *mut usize) -> Result<*mut c_char, Error> {
+    let digest = cache::checksum_file(filepath, algorithm, false).map_err(|err| Error::from_io(&err, &filepath.to_string_lossy()))?;
+    malloc_encoded(&digest, encoding, out_len)
+}
 
+/// Returns the SHA-256 digest of the file at `filepath` as a lowercase hex string, or NULL on
+/// failure, in which case `file_checksum_last_error_code` says why. The result must be freed with
+/// `release_checksum`.
+///
+/// On Unix `filepath` is taken as raw bytes, so names that aren't UTF-8 work. On Windows it must
+/// be UTF-8 or the call fails with `InvalidUtf8`.
+///
+/// # Safety
+///
+/// `filepath` must be NULL or point to a valid NUL terminated string.
 #[no_mangle]
-pub extern "C" fn get_checksum(filepath: *const c_char) -> *mut c_char {
-    // Your code here
-    if filepath == ptr::null() {
-      return ptr::null_mut::<c_char>()
+pub unsafe extern "C" fn get_checksum(filepath: *const c_char) -> *mut c_char {
+    error::guard(ptr::null_mut(), || checksum_to_c_string(filepath, Algorithm::default(), Encoding::HexLower, ptr::null_mut()))
+}
+
+/// Like `get_checksum` but with the digest chosen by `algorithm`, one of the `Algorithm` values.
+/// The result must be freed with `release_checksum`.
+///
+/// # Safety
+///
+/// `filepath` must be NULL or point to a valid NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn get_checksum_with_algorithm(filepath: *const c_char, algorithm: c_int) -> *mut c_char {
+    error::guard(ptr::null_mut(), || checksum_to_c_string(filepath, algorithm_from_c(algorithm)?, Encoding::HexLower, ptr::null_mut()))
+}
+
+/// Like `get_checksum_with_algorithm` but with control over the digest cache opened by
+/// `file_checksum_cache_open`. When `force` is true the file is always read and the cached digest
+/// replaced, otherwise an unchanged file is answered from the cache. Without an open cache it is
+/// the same as `get_checksum_with_algorithm`. The result must be freed with `release_checksum`.
+///
+/// # Safety
+///
+/// `filepath` must be NULL or point to a valid NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn get_checksum_cached(filepath: *const c_char, algorithm: c_int, force: bool) -> *mut c_char {
+    error::guard(ptr::null_mut(), || {
+        let algorithm = algorithm_from_c(algorithm)?;
+        let filepath = path_from_c(filepath)?;
+        let digest = cache::checksum_file(filepath, algorithm, force).map_err(|err| Error::from_io(&err, &filepath.to_string_lossy()))?;
+        malloc_c_string(&checksum::to_hex(&digest))
+    })
+}
+
+/// Like `get_checksum_with_algorithm` with the digest returned as `encoding`, one of the
+/// `Encoding` values. The result is always NUL terminated and `*out_len`, if `out_len` isn't
+/// NULL, receives its length excluding the terminator. `out_len` is required for `Raw` output,
+/// which is the digest bytes themselves and may contain NULs. The result must be freed with
+/// `release_checksum`.
+///
+/// # Safety
+///
+/// `filepath` must be NULL or point to a valid NUL terminated string and `out_len` must be NULL or
+/// point to a writable `size_t`.
+#[no_mangle]
+pub unsafe extern "C" fn get_checksum_encoded(filepath: *const c_char, algorithm: c_int, encoding: c_int, out_len: *mut usize) -> *mut c_char {
+    error::guard(ptr::null_mut(), || {
+        checksum_to_c_string(filepath, algorithm_from_c(algorithm)?, encoding_from_c(encoding)?, out_len)
+    })
+}
+
+/// Receives progress from `get_checksum_with_progress`: the bytes hashed so far, the size of the
+/// file, or 0 if it isn't known, and the caller's `user_data`. Returning non-zero cancels.
+pub type ProgressCallback = Option<unsafe extern "C" fn(processed: u64, total: u64, user_data: *mut c_void) -> c_int>;
+
+unsafe fn checksum_with_progress(filepath: *const c_char, callback: ProgressCallback, user_data: *mut c_void) -> Result<*mut c_char, Error> {
+    let filepath = path_from_c(filepath)?;
+    let progress = |processed, total| callback.is_none_or(|callback| callback(processed, total, user_data) == 0);
+    let digest = checksum::checksum_file_with_progress(filepath, Algorithm::default(), progress)
+        .map_err(|err| Error::from_io(&err, &filepath.to_string_lossy()))?
+        .ok_or_else(|| Error::new(ErrorCode::Cancelled, format!("{}: cancelled", filepath.display())))?;
+    malloc_c_string(&checksum::to_hex(&digest))
+}
+
+/// Like `get_checksum` but calls `callback`, if it isn't NULL, once before reading and then after
+/// every chunk read, on the calling thread. If the callback returns non-zero the file is closed,
+/// NULL is returned and the last error is `Cancelled`. The result must be freed with
+/// `release_checksum`.
+///
+/// # Safety
+///
+/// `filepath` must be NULL or point to a valid NUL terminated string. `user_data` is passed to the
+/// callback untouched.
+#[no_mangle]
+pub unsafe extern "C" fn get_checksum_with_progress(filepath: *const c_char, callback: ProgressCallback, user_data: *mut c_void) -> *mut c_char {
+    error::guard(ptr::null_mut(), || checksum_with_progress(filepath, callback, user_data))
+}
+
+/// Like `get_checksum_encoded` with the path given as `path_len` bytes, which needn't be NUL
+/// terminated or UTF-8. On Unix the bytes are the file name exactly as the file system stores it,
+/// e.g. Latin-1 from an older system or a Python `bytes` path. On Windows they must be UTF-8. A NUL
+/// byte anywhere in the path fails with `InvalidArgument`. The result must be freed with
+/// `release_checksum`.
+///
+/// # Safety
+///
+/// `filepath` must be NULL or point to at least `path_len` readable bytes and `out_len` must be
+/// NULL or point to a writable `size_t`.
+#[no_mangle]
+pub unsafe extern "C" fn get_checksum_path_bytes(filepath: *const u8, path_len: usize, algorithm: c_int, encoding: c_int, out_len: *mut usize) -> *mut c_char {
+    error::guard(ptr::null_mut(), || {
+        let filepath = path_from_c_bytes(filepath, path_len)?;
+        checksum_path_to_c_string(filepath, algorithm_from_c(algorithm)?, encoding_from_c(encoding)?, out_len)
+    })
+}
+
+unsafe fn checksum_bytes_to_c_string(data: *const u8, len: usize, algorithm: Algorithm, encoding: Encoding, out_len: *mut usize) -> Result<*mut c_char, Error> {
+    let data = if len == 0 {
+        &[][..]
+    } else if data.is_null() {
+        return Err(Error::new(ErrorCode::NullArgument, "data is NULL"));
+    } else {
+        slice::from_raw_parts(data, len)
+    };
+    malloc_encoded(&checksum::checksum_bytes(data, algorithm), encoding, out_len)
+}
+
+/// Returns the digest of the `len` bytes at `data` as a lowercase hex string, for data that is
+/// already in memory such as a Python `bytes` or `memoryview`. `algorithm` is one of the
+/// `Algorithm` values. Returns NULL on failure. The result must be freed with `release_checksum`.
+///
+/// # Safety
+///
+/// `data` must point to at least `len` readable bytes, or may be NULL if `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn checksum_bytes(data: *const u8, len: usize, algorithm: c_int) -> *mut c_char {
+    error::guard(ptr::null_mut(), || {
+        checksum_bytes_to_c_string(data, len, algorithm_from_c(algorithm)?, Encoding::HexLower, ptr::null_mut())
+    })
+}
+
+/// Like `checksum_bytes` with the digest returned as `encoding`, see `get_checksum_encoded`. The
+/// result must be freed with `release_checksum`.
+///
+/// # Safety
+///
+/// As for `checksum_bytes`, and `out_len` must be NULL or point to a writable `size_t`.
+#[no_mangle]
+pub unsafe extern "C" fn checksum_bytes_encoded(data: *const u8, len: usize, algorithm: c_int, encoding: c_int, out_len: *mut usize) -> *mut c_char {
+    error::guard(ptr::null_mut(), || {
+        checksum_bytes_to_c_string(data, len, algorithm_from_c(algorithm)?, encoding_from_c(encoding)?, out_len)
+    })
+}
+
+/// Borrows an open file descriptor as a `File` that is never closed by Rust.
+#[cfg(unix)]
+unsafe fn borrow_fd(fd: c_int) -> Result<ManuallyDrop<File>, Error> {
+    use std::os::unix::io::FromRawFd;
+    if fd < 0 {
+        return Err(Error::new(ErrorCode::InvalidArgument, format!("invalid file descriptor {}", fd)));
     }
+    Ok(ManuallyDrop::new(File::from_raw_fd(fd)))
+}
 
-    unsafe {
-        let result = malloc(12);
-        memset(result, 0, 12);
-        strcpy(result as *mut c_char, CString::new("abcdef").unwrap().as_ptr());
-        return result as *mut c_char;
+#[cfg(windows)]
+unsafe fn borrow_fd(fd: c_int) -> Result<ManuallyDrop<File>, Error> {
+    use std::os::windows::io::{FromRawHandle, RawHandle};
+    let handle = libc::get_osfhandle(fd);
+    if handle == -1 {
+        return Err(Error::new(ErrorCode::InvalidArgument, format!("invalid file descriptor {}", fd)));
     }
+    Ok(ManuallyDrop::new(File::from_raw_handle(handle as RawHandle)))
 }
 
+unsafe fn checksum_fd_to_c_string(fd: c_int, algorithm: Algorithm, encoding: Encoding, out_len: *mut usize) -> Result<*mut c_char, Error> {
+    let mut file = borrow_fd(fd)?;
+    let digest = checksum::checksum_open_file(&mut file, algorithm, checksum::mmap_threshold(), |_, _| true)
+        .map_err(|err| Error::from_io(&err, &format!("fd {}", fd)))?
+        .expect("never cancelled");
+    malloc_encoded(&digest, encoding, out_len)
+}
+
+/// Returns the digest of everything that can be read from the open file descriptor `fd`, from its
+/// current position to the end, as a lowercase hex string. It works for files, pipes, sockets and
+/// stdin alike. The descriptor is not closed and is left at the end of the data. `algorithm` is
+/// one of the `Algorithm` values. Returns NULL on failure. The result must be freed with
+/// `release_checksum`.
+///
+/// # Safety
+///
+/// `fd` must be an open descriptor that nothing else reads from during the call.
 #[no_mangle]
-pub extern "C" fn release_checksum(checksum: *const c_char) {
-    unsafe {
-        free(checksum as *mut c_void);
+pub unsafe extern "C" fn checksum_fd(fd: c_int, algorithm: c_int) -> *mut c_char {
+    error::guard(ptr::null_mut(), || {
+        checksum_fd_to_c_string(fd, algorithm_from_c(algorithm)?, Encoding::HexLower, ptr::null_mut())
+    })
+}
+
+/// Like `checksum_fd` with the digest returned as `encoding`, see `get_checksum_encoded`. The
+/// result must be freed with `release_checksum`.
+///
+/// # Safety
+///
+/// As for `checksum_fd`, and `out_len` must be NULL or point to a writable `size_t`.
+#[no_mangle]
+pub unsafe extern "C" fn checksum_fd_encoded(fd: c_int, algorithm: c_int, encoding: c_int, out_len: *mut usize) -> *mut c_char {
+    error::guard(ptr::null_mut(), || {
+        checksum_fd_to_c_string(fd, algorithm_from_c(algorithm)?, encoding_from_c(encoding)?, out_len)
+    })
+}
+
+unsafe fn checksum_into_c_buffer(filepath: *const c_char, algorithm: Algorithm, out_buf: *mut c_char, out_len: usize, written: *mut usize) -> Result<(), Error> {
+    let filepath = path_from_c(filepath)?;
+    // The length of the result is known up front so don't read the file just to report that the
+    // buffer is too small
+    check_c_buffer(algorithm.digest_len() * 2, out_buf, out_len, written)?;
+    let digest = cache::checksum_file(filepath, algorithm, false).map_err(|err| Error::from_io(&err, &filepath.to_string_lossy()))?;
+    copy_to_c_buffer(&checksum::to_hex(&digest), out_buf, out_len, written)
+}
+
+/// Writes the SHA-256 digest of the file at `filepath` as a NUL terminated lowercase hex string
+/// into the caller's `out_buf` of `out_len` bytes, so nothing needs to be released afterwards.
+/// Like `snprintf`, `*written` receives the length of the string excluding the terminator even if
+/// the buffer is too small, in which case `BufferTooSmall` is returned and the file isn't read.
+/// Pass a NULL `out_buf` and 0 `out_len` to query the size. Returns 0 on success or an
+/// `ErrorCode`.
+///
+/// # Safety
+///
+/// `filepath` must be NULL or point to a valid NUL terminated string, `out_buf` must be NULL or
+/// point to at least `out_len` writable bytes and `written` must be NULL or point to a writable
+/// `size_t`.
+#[no_mangle]
+pub unsafe extern "C" fn get_checksum_into(filepath: *const c_char, out_buf: *mut c_char, out_len: usize, written: *mut usize) -> c_int {
+    error::guard_status(|| checksum_into_c_buffer(filepath, Algorithm::default(), out_buf, out_len, written)) as c_int
+}
+
+/// Starts an incremental digest with `algorithm`, one of the `Algorithm` values. Feed it with
+/// `checksum_update`, read the result with `checksum_finalize` and always release the handle with
+/// `checksum_free`. Returns NULL if the algorithm is unknown.
+#[no_mangle]
+pub extern "C" fn checksum_new(algorithm: c_int) -> *mut ChecksumHandle {
+    error::guard(ptr::null_mut(), || algorithm_from_c(algorithm).map(handle::new_handle))
+}
+
+/// Adds `len` bytes at `data` to the digest. Returns 0 on success or an `ErrorCode`, e.g. if the
+/// handle was already finalized or freed.
+///
+/// # Safety
+///
+/// `data` must point to at least `len` readable bytes, or may be NULL if `len` is 0. The handle
+/// must not be used from more than one thread at a time.
+#[no_mangle]
+pub unsafe extern "C" fn checksum_update(handle: *mut ChecksumHandle, data: *const u8, len: usize) -> c_int {
+    let result = error::guard_status(|| {
+        let hasher = handle::hasher_mut(handle)?;
+        if len == 0 {
+            Ok(())
+        } else if data.is_null() {
+            Err(Error::new(ErrorCode::NullArgument, "data is NULL"))
+        } else {
+            hasher.update(slice::from_raw_parts(data, len));
+            Ok(())
+        }
+    });
+    result as c_int
+}
+
+/// Finishes the digest and returns it as a lowercase hex string, or NULL on failure. The result
+/// must be freed with `release_checksum`. The handle can't be updated afterwards but must still be
+/// freed with `checksum_free`.
+///
+/// # Safety
+///
+/// The handle must not be used from more than one thread at a time.
+#[no_mangle]
+pub unsafe extern "C" fn checksum_finalize(handle: *mut ChecksumHandle) -> *mut c_char {
+    error::guard(ptr::null_mut(), || malloc_c_string(&checksum::to_hex(&handle::finalize_handle(handle)?)))
+}
+
+/// Releases a handle from `checksum_new`. Returns 0 on success or an `ErrorCode` if the handle is
+/// NULL or was already freed.
+///
+/// # Safety
+///
+/// The handle must not be used from more than one thread at a time.
+#[no_mangle]
+pub unsafe extern "C" fn checksum_free(handle: *mut ChecksumHandle) -> c_int {
+    error::guard_status(|| handle::free_handle(handle)) as c_int
+}
+
+/// What kind of file a `ChecksumResult` describes. Symbolic links are followed when the file is
+/// opened, so they are never reported. The values are part of the C ABI and must not change.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    /// Not known, because the file couldn't be opened or the platform can't tell.
+    Unknown = 0,
+    Regular = 1,
+    Directory = 2,
+    /// A named pipe.
+    Fifo = 3,
+    CharDevice = 4,
+    BlockDevice = 5,
+    Socket = 6,
+}
+
+impl FileType {
+    #[cfg(unix)]
+    fn of(file_type: fs::FileType) -> FileType {
+        use std::os::unix::fs::FileTypeExt;
+        if file_type.is_file() {
+            FileType::Regular
+        } else if file_type.is_dir() {
+            FileType::Directory
+        } else if file_type.is_fifo() {
+            FileType::Fifo
+        } else if file_type.is_char_device() {
+            FileType::CharDevice
+        } else if file_type.is_block_device() {
+            FileType::BlockDevice
+        } else if file_type.is_socket() {
+            FileType::Socket
+        } else {
+            FileType::Unknown
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn of(file_type: fs::FileType) -> FileType {
+        if file_type.is_file() {
+            FileType::Regular
+        } else if file_type.is_dir() {
+            FileType::Directory
+        } else {
+            FileType::Unknown
+        }
+    }
+}
+
+/// Everything about one checksummed file, filled in by `get_checksum_result`. The digest is held
+/// inline, only `error_message` lives on the heap and is freed by `release_checksum_result`.
+#[repr(C)]
+pub struct ChecksumResult {
+    /// 0 on success or an `ErrorCode`. On failure only `algorithm` and `error_message` may be set.
+    pub status: c_int,
+    /// One of the `Algorithm` values.
+    pub algorithm: c_int,
+    /// The first `digest_len` bytes are the digest.
+    pub digest: [u8; MAX_DIGEST_LEN],
+    pub digest_len: usize,
+    /// Size of the file when it was opened, 0 for anything but a regular file.
+    pub size: u64,
+    /// Last modification time in seconds since the Unix epoch, negative before 1970.
+    pub mtime_secs: i64,
+    pub mtime_nanos: u32,
+    /// Bytes hashed, which differs from `size` if the file changed while it was read.
+    pub bytes_read: u64,
+    /// Description of the failure or NULL on success.
+    pub error_message: *mut c_char,
+    /// One of the `FileType` values.
+    pub file_type: c_int,
+}
+
+impl ChecksumResult {
+    fn empty() -> ChecksumResult {
+        ChecksumResult {
+            status: ErrorCode::Ok as c_int,
+            algorithm: 0,
+            digest: [0; MAX_DIGEST_LEN],
+            digest_len: 0,
+            size: 0,
+            mtime_secs: 0,
+            mtime_nanos: 0,
+            bytes_read: 0,
+            error_message: ptr::null_mut(),
+            file_type: FileType::Unknown as c_int,
+        }
+    }
+}
+
+/// Splits a modification time into whole seconds and nanoseconds either side of the Unix epoch,
+/// with the nanoseconds always counting forwards.
+fn unix_time(time: SystemTime) -> (i64, u32) {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => (after.as_secs() as i64, after.subsec_nanos()),
+        Err(err) => {
+            let before = err.duration();
+            match before.subsec_nanos() {
+                0 => (-(before.as_secs() as i64), 0),
+                nanos => (-(before.as_secs() as i64) - 1, 1_000_000_000 - nanos),
+            }
+        }
+    }
+}
+
+unsafe fn fill_checksum_result(filepath: *const c_char, algorithm: c_int, result: &mut ChecksumResult) -> Result<(), Error> {
+    let filepath = path_from_c(filepath)?;
+    let algorithm = algorithm_from_c(algorithm)?;
+    result.algorithm = algorithm as c_int;
+
+    let to_error = |err| Error::from_io(&err, &filepath.to_string_lossy());
+    let mut file = File::open(filepath).map_err(to_error)?;
+    let metadata = file.metadata().map_err(to_error)?;
+    let (mtime_secs, mtime_nanos) = metadata.modified().map(unix_time).unwrap_or((0, 0));
+    let mut bytes_read = 0;
+    let digest = checksum::checksum_open_file(&mut file, algorithm, checksum::mmap_threshold(), |processed, _| {
+        bytes_read = processed;
+        true
+    })
+    .map_err(to_error)?
+    .expect("never cancelled");
+
+    result.digest[..digest.len()].copy_from_slice(&digest);
+    result.digest_len = digest.len();
+    result.file_type = FileType::of(metadata.file_type()) as c_int;
+    result.size = if metadata.is_file() { metadata.len() } else { 0 };
+    result.mtime_secs = mtime_secs;
+    result.mtime_nanos = mtime_nanos;
+    result.bytes_read = bytes_read;
+    Ok(())
+}
+
+/// Checksums the file at `filepath` with `algorithm`, one of the `Algorithm` values, and fills
+/// `*result` with the digest and what a `stat` of the open file says, so no second system call or
+/// string parsing is needed. The whole file is always read, the digest cache isn't used. Returns
+/// the same 0 or `ErrorCode` as is stored in `result->status`. Whatever the outcome, release the
+/// result with `release_checksum_result` before it is filled again or discarded.
+///
+/// # Safety
+///
+/// `filepath` must be NULL or point to a valid NUL terminated string and `result` must be NULL or
+/// point to a writable `ChecksumResult`. Its previous contents are overwritten, not released.
+#[no_mangle]
+pub unsafe extern "C" fn get_checksum_result(filepath: *const c_char, algorithm: c_int, result: *mut ChecksumResult) -> c_int {
+    if result.is_null() {
+        return error::guard_status(|| Err(Error::new(ErrorCode::NullArgument, "result is NULL"))) as c_int;
+    }
+    ptr::write(result, ChecksumResult::empty());
+    let status = error::guard_status(|| fill_checksum_result(filepath, algorithm, &mut *result));
+    (*result).status = status as c_int;
+    if status != ErrorCode::Ok {
+        (*result).error_message = error::with_last_error_message(|message| message.map_or(ptr::null_mut(), |message| message.clone().into_raw()));
+    }
+    status as c_int
+}
+
+/// Frees the heap owned fields of a result filled by `get_checksum_result` and sets them to NULL.
+/// The record itself belongs to the caller. Releasing a result twice is harmless.
+///
+/// # Safety
+///
+/// `result` must be NULL or point to a `ChecksumResult` filled by `get_checksum_result`.
+#[no_mangle]
+pub unsafe extern "C" fn release_checksum_result(result: *mut ChecksumResult) {
+    if result.is_null() {
+        return;
+    }
+    error::guard_silent((), || {
+        let message = ptr::replace(&mut (*result).error_message, ptr::null_mut());
+        if !message.is_null() {
+            drop(CString::from_raw(message));
+        }
+    })
+}
+
+/// Writes up to `capacity` entries describing the supported algorithms into `infos` and returns
+/// the total number of supported algorithms. Call with a NULL `infos` to find out how many
+/// entries to allocate.
+///
+/// # Safety
+///
+/// `infos` must be NULL or point to at least `capacity` writable `AlgorithmInfo` entries.
+#[no_mangle]
+pub unsafe extern "C" fn get_supported_algorithms(infos: *mut AlgorithmInfo, capacity: usize) -> usize {
+    error::guard_silent(0, || {
+        if !infos.is_null() {
+            for (i, algorithm) in Algorithm::ALL.iter().take(capacity).enumerate() {
+                *infos.add(i) = AlgorithmInfo {
+                    algorithm: *algorithm as c_int,
+                    name: algorithm_c_name(*algorithm).as_ptr() as *const c_char,
+                    digest_len: algorithm.digest_len(),
+                };
+            }
+        }
+        Algorithm::ALL.len()
+    })
+}
+
+/// Frees a string returned by `get_checksum`.
+///
+/// # Safety
+///
+/// `checksum` must be NULL or a pointer returned by this library that has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn release_checksum(checksum: *const c_char) {
+    error::guard_silent((), || free(checksum as *mut c_void))
+}
+
+/// Sets the size in bytes from which regular files are hashed through a read-only memory map
+/// instead of buffered reads, for every thread. 0 turns memory maps off.
+#[no_mangle]
+pub extern "C" fn file_checksum_set_mmap_threshold(threshold: u64) {
+    error::guard_silent((), || checksum::set_mmap_threshold(threshold))
+}
+
+/// Returns the current memory map threshold, see `file_checksum_set_mmap_threshold`.
+#[no_mangle]
+pub extern "C" fn file_checksum_mmap_threshold() -> u64 {
+    error::guard_silent(0, checksum::mmap_threshold)
+}
+
+/// The version of the C interface. It goes up whenever an export is removed or its signature, a
+/// struct layout or an enum value changes, but not when something is added, so a loader built
+/// against one version can refuse a library with any other.
+pub const ABI_VERSION: u32 = 2;
+
+/// Returns `ABI_VERSION` as the library was built with it, for comparing against the value in the
+/// header a program was compiled with.
+#[no_mangle]
+pub extern "C" fn file_checksum_abi_version() -> u32 {
+    ABI_VERSION
+}
+
+/// Returns the `ErrorCode` of the last call into the library made on the calling thread, or 0 if
+/// it succeeded.
+#[no_mangle]
+pub extern "C" fn file_checksum_last_error_code() -> c_int {
+    error::guard_silent(ErrorCode::Panic, error::last_error_code) as c_int
+}
+
+/// Returns a description of the last error on the calling thread, or NULL if the last call
+/// succeeded. The string is owned by the library and stays valid until the next call into the
+/// library on the same thread.
+#[no_mangle]
+pub extern "C" fn file_checksum_last_error_message() -> *const c_char {
+    error::guard_silent(ptr::null(), || {
+        error::with_last_error_message(|message| message.map_or(ptr::null(), |message| message.as_ptr()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::ffi::{CStr, CString};
+    use std::fs;
+
+    unsafe fn take_c_string(result: *mut c_char) -> String {
+        assert!(!result.is_null());
+        let value = CStr::from_ptr(result).to_str().unwrap().to_string();
+        release_checksum(result);
+        value
+    }
+
+    #[test]
+    fn get_checksum_of_file() {
+        let path = temp_dir().join("file_checksum_get_checksum.txt");
+        fs::write(&path, b"abc").unwrap();
+        let filepath = CString::new(path.to_str().unwrap()).unwrap();
+        unsafe {
+            assert_eq!(
+                take_c_string(get_checksum(filepath.as_ptr())),
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+            );
+            assert_eq!(
+                take_c_string(get_checksum_with_algorithm(filepath.as_ptr(), Algorithm::Md5 as c_int)),
+                "900150983cd24fb0d6963f7d28e17f72"
+            );
+            assert!(get_checksum_with_algorithm(filepath.as_ptr(), 99).is_null());
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn get_checksum_null_and_missing() {
+        let missing = CString::new("/this/path/does/not/exist").unwrap();
+        unsafe {
+            assert!(get_checksum(ptr::null()).is_null());
+            assert_eq!(file_checksum_last_error_code(), ErrorCode::NullArgument as c_int);
+            assert!(get_checksum(missing.as_ptr()).is_null());
+            assert_eq!(file_checksum_last_error_code(), ErrorCode::NotFound as c_int);
+            let message = CStr::from_ptr(file_checksum_last_error_message()).to_str().unwrap();
+            assert!(message.starts_with("/this/path/does/not/exist: "));
+        }
+    }
+
+    #[test]
+    fn error_codes() {
+        let invalid_utf8 = CString::new(vec![b'/', 0xff, 0xfe]).unwrap();
+        unsafe {
+            assert!(get_checksum(invalid_utf8.as_ptr()).is_null());
+            if cfg!(unix) {
+                assert_eq!(file_checksum_last_error_code(), ErrorCode::NotFound as c_int);
+            } else {
+                assert_eq!(file_checksum_last_error_code(), ErrorCode::InvalidUtf8 as c_int);
+            }
+            assert!(get_checksum_with_algorithm(invalid_utf8.as_ptr(), 42).is_null());
+            assert_eq!(file_checksum_last_error_code(), ErrorCode::UnsupportedAlgorithm as c_int);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_file_names() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+        // "café.txt" in Latin-1
+        let mut name = temp_dir().join("file_checksum_").into_os_string().into_vec();
+        name.extend_from_slice(b"caf\xe9.txt");
+        let path = Path::new(OsStr::from_bytes(&name));
+        if fs::write(path, b"abc").is_err() {
+            // Some file systems insist on UTF-8 names
+            return;
+        }
+        let md5 = Algorithm::Md5 as c_int;
+        let hex = Encoding::HexLower as c_int;
+        unsafe {
+            let filepath = CString::new(name.clone()).unwrap();
+            assert_eq!(take_c_string(get_checksum_with_algorithm(filepath.as_ptr(), md5)), "900150983cd24fb0d6963f7d28e17f72");
+            assert_eq!(
+                take_c_string(get_checksum_path_bytes(name.as_ptr(), name.len(), md5, hex, ptr::null_mut())),
+                "900150983cd24fb0d6963f7d28e17f72"
+            );
+
+            // The error message shows the undecodable byte as U+FFFD
+            let missing = b"/this/path/does/not/exist/caf\xe9";
+            assert!(get_checksum_path_bytes(missing.as_ptr(), missing.len(), md5, hex, ptr::null_mut()).is_null());
+            assert_eq!(file_checksum_last_error_code(), ErrorCode::NotFound as c_int);
+            let message = CStr::from_ptr(file_checksum_last_error_message()).to_str().unwrap();
+            assert!(message.starts_with("/this/path/does/not/exist/caf\u{fffd}: "));
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn path_bytes_arguments() {
+        let path = temp_dir().join("file_checksum_path_bytes.txt");
+        fs::write(&path, b"abc").unwrap();
+        let name = path.to_str().unwrap().as_bytes();
+        let md5 = Algorithm::Md5 as c_int;
+        let hex = Encoding::HexLower as c_int;
+        unsafe {
+            // The length is all that counts, so a longer buffer is fine
+            let padded = [name, b"trailing"].concat();
+            assert_eq!(
+                take_c_string(get_checksum_path_bytes(padded.as_ptr(), name.len(), md5, hex, ptr::null_mut())),
+                "900150983cd24fb0d6963f7d28e17f72"
+            );
+            let with_nul = [name, b"\0"].concat();
+            assert!(get_checksum_path_bytes(with_nul.as_ptr(), with_nul.len(), md5, hex, ptr::null_mut()).is_null());
+            assert_eq!(file_checksum_last_error_code(), ErrorCode::InvalidArgument as c_int);
+            assert!(get_checksum_path_bytes(ptr::null(), 0, md5, hex, ptr::null_mut()).is_null());
+            assert_eq!(file_checksum_last_error_code(), ErrorCode::NullArgument as c_int);
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn success_clears_last_error() {
+        let path = temp_dir().join("file_checksum_clears_error.txt");
+        fs::write(&path, b"").unwrap();
+        let filepath = CString::new(path.to_str().unwrap()).unwrap();
+        unsafe {
+            assert!(get_checksum(ptr::null()).is_null());
+            take_c_string(get_checksum(filepath.as_ptr()));
+            assert_eq!(file_checksum_last_error_code(), ErrorCode::Ok as c_int);
+            assert!(file_checksum_last_error_message().is_null());
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    unsafe extern "C" fn record_progress(processed: u64, total: u64, user_data: *mut c_void) -> c_int {
+        let calls = &mut *(user_data as *mut Vec<(u64, u64)>);
+        calls.push((processed, total));
+        0
+    }
+
+    unsafe extern "C" fn cancel_progress(processed: u64, _total: u64, _user_data: *mut c_void) -> c_int {
+        (processed > 0) as c_int
+    }
+
+    #[test]
+    fn get_checksum_with_progress_callback() {
+        let path = temp_dir().join("file_checksum_progress.bin");
+        let size = checksum::CHUNK_SIZE as u64 + 10;
+        fs::write(&path, vec![1u8; size as usize]).unwrap();
+        let filepath = CString::new(path.to_str().unwrap()).unwrap();
+        unsafe {
+            let mut calls: Vec<(u64, u64)> = Vec::new();
+            let user_data = &mut calls as *mut Vec<(u64, u64)> as *mut c_void;
+            let result = take_c_string(get_checksum_with_progress(filepath.as_ptr(), Some(record_progress), user_data));
+            assert_eq!(result, take_c_string(get_checksum(filepath.as_ptr())));
+            assert_eq!(calls, vec![(0, size), (checksum::CHUNK_SIZE as u64, size), (size, size)]);
+
+            assert!(get_checksum_with_progress(filepath.as_ptr(), Some(cancel_progress), ptr::null_mut()).is_null());
+            assert_eq!(file_checksum_last_error_code(), ErrorCode::Cancelled as c_int);
+
+            take_c_string(get_checksum_with_progress(filepath.as_ptr(), None, ptr::null_mut()));
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn checksum_of_bytes() {
+        unsafe {
+            assert_eq!(
+                take_c_string(checksum_bytes(b"abc".as_ptr(), 3, Algorithm::Sha1 as c_int)),
+                "a9993e364706816aba3e25717850c26c9cd0d89d"
+            );
+            assert_eq!(take_c_string(checksum_bytes(ptr::null(), 0, Algorithm::Crc32 as c_int)), "00000000");
+            assert!(checksum_bytes(ptr::null(), 1, Algorithm::Crc32 as c_int).is_null());
+            assert_eq!(file_checksum_last_error_code(), ErrorCode::NullArgument as c_int);
+            assert!(checksum_bytes(b"abc".as_ptr(), 3, 77).is_null());
+            assert_eq!(file_checksum_last_error_code(), ErrorCode::UnsupportedAlgorithm as c_int);
+        }
+    }
+
+    #[test]
+    fn encoded_checksums() {
+        let path = temp_dir().join("file_checksum_encoded.txt");
+        fs::write(&path, b"").unwrap();
+        let filepath = CString::new(path.to_str().unwrap()).unwrap();
+        let md5 = Algorithm::Md5 as c_int;
+        unsafe {
+            let mut len = 0;
+            let result = get_checksum_encoded(filepath.as_ptr(), md5, Encoding::Base64 as c_int, &mut len);
+            assert_eq!(len, 24);
+            assert_eq!(take_c_string(result), "1B2M2Y8AsgTpgAmY7PhCfg==");
+            assert_eq!(
+                take_c_string(get_checksum_encoded(filepath.as_ptr(), md5, Encoding::HexUpper as c_int, ptr::null_mut())),
+                "D41D8CD98F00B204E9800998ECF8427E"
+            );
+            assert_eq!(
+                take_c_string(checksum_bytes_encoded(ptr::null(), 0, md5, Encoding::Base64Url as c_int, ptr::null_mut())),
+                "1B2M2Y8AsgTpgAmY7PhCfg"
+            );
+            assert_eq!(
+                take_c_string(checksum_bytes_encoded(b"abc".as_ptr(), 3, Algorithm::Crc32 as c_int, Encoding::Base32 as c_int, ptr::null_mut())),
+                "GUSEDQQ="
+            );
+
+            let result = checksum_bytes_encoded(b"abc".as_ptr(), 3, Algorithm::Crc32 as c_int, Encoding::Raw as c_int, &mut len);
+            assert_eq!(slice::from_raw_parts(result as *const u8, len), [0x35, 0x24, 0x41, 0xc2]);
+            release_checksum(result);
+
+            // Raw output can't be measured by its terminator
+            assert!(get_checksum_encoded(filepath.as_ptr(), md5, Encoding::Raw as c_int, ptr::null_mut()).is_null());
+            assert_eq!(file_checksum_last_error_code(), ErrorCode::NullArgument as c_int);
+            assert!(get_checksum_encoded(filepath.as_ptr(), md5, 42, &mut len).is_null());
+            assert_eq!(file_checksum_last_error_code(), ErrorCode::InvalidArgument as c_int);
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn checksum_of_fd() {
+        use std::io::{Read, Write};
+        use std::os::unix::io::{AsRawFd, FromRawFd};
+
+        let path = temp_dir().join("file_checksum_fd.txt");
+        fs::write(&path, b"skip abc").unwrap();
+        let mut file = File::open(&path).unwrap();
+        let mut skipped = [0u8; 5];
+        file.read_exact(&mut skipped).unwrap();
+        unsafe {
+            assert_eq!(
+                take_c_string(checksum_fd(file.as_raw_fd(), Algorithm::Md5 as c_int)),
+                "900150983cd24fb0d6963f7d28e17f72"
+            );
+            // The descriptor is still open and at the end
+            assert_eq!(file.read(&mut skipped).unwrap(), 0);
+
+            let mut fds = [0 as c_int; 2];
+            assert_eq!(libc::pipe(fds.as_mut_ptr()), 0);
+            let mut writer = File::from_raw_fd(fds[1]);
+            writer.write_all(b"abc").unwrap();
+            drop(writer);
+            assert_eq!(
+                take_c_string(checksum_fd(fds[0], Algorithm::Md5 as c_int)),
+                "900150983cd24fb0d6963f7d28e17f72"
+            );
+            libc::close(fds[0]);
+
+            assert!(checksum_fd(-1, Algorithm::Md5 as c_int).is_null());
+            assert_eq!(file_checksum_last_error_code(), ErrorCode::InvalidArgument as c_int);
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn get_checksum_into_buffer() {
+        let path = temp_dir().join("file_checksum_into.txt");
+        fs::write(&path, b"abc").unwrap();
+        let filepath = CString::new(path.to_str().unwrap()).unwrap();
+        unsafe {
+            let mut written = 0;
+            let status = get_checksum_into(filepath.as_ptr(), ptr::null_mut(), 0, &mut written);
+            assert_eq!(status, ErrorCode::BufferTooSmall as c_int);
+            assert_eq!(written, 64);
+
+            // One byte short, there is no room for the terminator
+            let mut buffer = vec![1 as c_char; 64];
+            let status = get_checksum_into(filepath.as_ptr(), buffer.as_mut_ptr(), buffer.len(), &mut written);
+            assert_eq!(status, ErrorCode::BufferTooSmall as c_int);
+            assert_eq!(buffer[0], 0);
+
+            let mut buffer = vec![1 as c_char; 65];
+            let status = get_checksum_into(filepath.as_ptr(), buffer.as_mut_ptr(), buffer.len(), ptr::null_mut());
+            assert_eq!(status, 0);
+            assert_eq!(
+                CStr::from_ptr(buffer.as_ptr()).to_str().unwrap(),
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+            );
+
+            let missing = CString::new("/this/path/does/not/exist").unwrap();
+            let status = get_checksum_into(missing.as_ptr(), buffer.as_mut_ptr(), buffer.len(), &mut written);
+            assert_eq!(status, ErrorCode::NotFound as c_int);
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn incremental_checksum() {
+        unsafe {
+            let handle = checksum_new(Algorithm::Sha256 as c_int);
+            assert!(!handle.is_null());
+            assert_eq!(checksum_update(handle, b"ab".as_ptr(), 2), 0);
+            assert_eq!(checksum_update(handle, ptr::null(), 0), 0);
+            assert_eq!(checksum_update(handle, b"c".as_ptr(), 1), 0);
+            assert_eq!(
+                take_c_string(checksum_finalize(handle)),
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+            );
+
+            assert_eq!(checksum_update(handle, b"d".as_ptr(), 1), ErrorCode::HandleFinalized as c_int);
+            assert!(checksum_finalize(handle).is_null());
+            assert_eq!(file_checksum_last_error_code(), ErrorCode::HandleFinalized as c_int);
+            assert_eq!(checksum_free(handle), 0);
+            assert_eq!(checksum_free(handle), ErrorCode::InvalidHandle as c_int);
+            assert_eq!(checksum_update(handle, b"d".as_ptr(), 1), ErrorCode::InvalidHandle as c_int);
+        }
+    }
+
+    #[test]
+    fn incremental_checksum_bad_arguments() {
+        unsafe {
+            assert!(checksum_new(-1).is_null());
+            assert_eq!(file_checksum_last_error_code(), ErrorCode::UnsupportedAlgorithm as c_int);
+            assert_eq!(checksum_free(ptr::null_mut()), ErrorCode::NullArgument as c_int);
+
+            let handle = checksum_new(Algorithm::Crc32 as c_int);
+            assert_eq!(checksum_update(handle, ptr::null(), 4), ErrorCode::NullArgument as c_int);
+            assert_eq!(checksum_free(handle), 0);
+        }
+    }
+
+    #[test]
+    fn supported_algorithms() {
+        unsafe {
+            let count = get_supported_algorithms(ptr::null_mut(), 0);
+            assert_eq!(count, Algorithm::ALL.len());
+
+            let empty = AlgorithmInfo { algorithm: -1, name: ptr::null(), digest_len: 0 };
+            let mut infos = vec![empty; count];
+            assert_eq!(get_supported_algorithms(infos.as_mut_ptr(), count), count);
+            for (info, algorithm) in infos.iter().zip(Algorithm::ALL.iter()) {
+                assert_eq!(info.algorithm, *algorithm as c_int);
+                assert_eq!(CStr::from_ptr(info.name).to_str().unwrap(), algorithm.name());
+                assert_eq!(info.digest_len, algorithm.digest_len());
+            }
+        }
+    }
+
+    #[test]
+    fn checksum_result_record() {
+        let path = temp_dir().join("file_checksum_result.txt");
+        fs::write(&path, b"abc").unwrap();
+        let filepath = CString::new(path.to_str().unwrap()).unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap().duration_since(UNIX_EPOCH).unwrap();
+        unsafe {
+            let mut result = ChecksumResult::empty();
+            assert_eq!(get_checksum_result(filepath.as_ptr(), Algorithm::Sha1 as c_int, &mut result), 0);
+            assert_eq!((result.status, result.algorithm), (0, Algorithm::Sha1 as c_int));
+            assert_eq!(&result.digest[..result.digest_len], &checksum::checksum_bytes(b"abc", Algorithm::Sha1)[..]);
+            assert!(result.digest[result.digest_len..].iter().all(|b| *b == 0));
+            assert_eq!((result.file_type, result.size, result.bytes_read), (FileType::Regular as c_int, 3, 3));
+            assert_eq!((result.mtime_secs as u64, result.mtime_nanos), (modified.as_secs(), modified.subsec_nanos()));
+            assert!(result.error_message.is_null());
+            release_checksum_result(&mut result);
+
+            let missing = CString::new("/this/path/does/not/exist").unwrap();
+            assert_eq!(get_checksum_result(missing.as_ptr(), Algorithm::Sha512 as c_int, &mut result), ErrorCode::NotFound as c_int);
+            assert_eq!((result.status, result.algorithm, result.digest_len), (ErrorCode::NotFound as c_int, Algorithm::Sha512 as c_int, 0));
+            assert_eq!(result.file_type, FileType::Unknown as c_int);
+            assert!(CStr::from_ptr(result.error_message).to_str().unwrap().starts_with("/this/path/does/not/exist: "));
+            release_checksum_result(&mut result);
+            assert!(result.error_message.is_null());
+            release_checksum_result(&mut result);
+
+            #[cfg(unix)]
+            {
+                let device = CString::new("/dev/null").unwrap();
+                assert_eq!(get_checksum_result(device.as_ptr(), Algorithm::Sha1 as c_int, &mut result), 0);
+                assert_eq!((result.file_type, result.size, result.bytes_read), (FileType::CharDevice as c_int, 0, 0));
+                release_checksum_result(&mut result);
+            }
+
+            assert_eq!(get_checksum_result(filepath.as_ptr(), 99, &mut result), ErrorCode::UnsupportedAlgorithm as c_int);
+            release_checksum_result(&mut result);
+            assert_eq!(get_checksum_result(filepath.as_ptr(), 0, ptr::null_mut()), ErrorCode::NullArgument as c_int);
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unix_times() {
+        use std::time::Duration;
+        assert_eq!(unix_time(UNIX_EPOCH + Duration::new(5, 7)), (5, 7));
+        assert_eq!(unix_time(UNIX_EPOCH - Duration::new(5, 0)), (-5, 0));
+        assert_eq!(unix_time(UNIX_EPOCH - Duration::new(5, 250_000_000)), (-6, 750_000_000));
     }
 }
@@ -0,0 +1,190 @@
+//! Diagnostics passed to a callback registered by the host application, such as which files are
+//! opened, when a memory map falls back to reads, cache hits and misses, and every error an
+//! exported call returns. Nothing is formatted unless a callback wants the level.
+
+use std::cell::Cell;
+use std::ffi::CString;
+use std::fmt;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{PoisonError, RwLock};
+use libc::{c_char, c_int, c_void};
+
+use error::{self, Error, ErrorCode};
+
+/// How serious a message is, most serious first. The values are part of the C ABI and must not
+/// change.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    /// An exported call failed.
+    Error = 0,
+    /// Something didn't go as planned but the call carried on, e.g. a memory map fell back to reads.
+    Warn = 1,
+    Info = 2,
+    /// What the library is doing with each file.
+    Debug = 3,
+    Trace = 4,
+}
+
+impl LogLevel {
+    /// cbindgen:ignore
+    pub const ALL: [LogLevel; 5] = [LogLevel::Error, LogLevel::Warn, LogLevel::Info, LogLevel::Debug, LogLevel::Trace];
+
+    /// Maps the integer passed across the C ABI back to a level.
+    pub fn from_raw(value: i32) -> Option<LogLevel> {
+        LogLevel::ALL.iter().cloned().find(|l| *l as i32 == value)
+    }
+}
+
+/// Receives a message at `level`, one of the `LogLevel` values, from the part of the library
+/// named by `target`, e.g. "cache". Both strings are only valid during the call.
+pub type LogCallback = Option<unsafe extern "C" fn(level: c_int, target: *const c_char, message: *const c_char, user_data: *mut c_void)>;
+
+struct Logger {
+    level: LogLevel,
+    callback: unsafe extern "C" fn(c_int, *const c_char, *const c_char, *mut c_void),
+    user_data: *mut c_void,
+}
+
+// The host promises the callback can be called from any thread with its user data
+unsafe impl Send for Logger {}
+unsafe impl Sync for Logger {}
+
+static LOGGER: RwLock<Option<Logger>> = RwLock::new(None);
+
+/// The most verbose level anyone wants, or -1 with no callback, checked before taking the lock.
+static MAX_LEVEL: AtomicI32 = AtomicI32::new(-1);
+
+thread_local! {
+    /// Set while the callback runs, so a callback that calls back into the library doesn't log
+    /// recursively or deadlock by replacing itself.
+    static IN_CALLBACK: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether a message at `level` would reach a callback.
+pub fn enabled(level: LogLevel) -> bool {
+    level as i32 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+fn c_string(value: &str) -> CString {
+    CString::new(value.replace('\0', "\\0")).expect("NULs were escaped")
+}
+
+/// Passes a message to the callback if one is registered for `level`. `args` is only formatted
+/// when it will be delivered.
+pub fn log(level: LogLevel, target: &str, args: fmt::Arguments) {
+    if !enabled(level) || IN_CALLBACK.with(Cell::get) {
+        return;
+    }
+    // The read lock is held during the callback so it can't be removed while it is running
+    let logger = LOGGER.read().unwrap_or_else(PoisonError::into_inner);
+    let logger = match *logger {
+        Some(ref logger) if level <= logger.level => logger,
+        _ => return,
+    };
+    let (target, message) = (c_string(target), c_string(&fmt::format(args)));
+    IN_CALLBACK.with(|in_callback| in_callback.set(true));
+    unsafe { (logger.callback)(level as c_int, target.as_ptr(), message.as_ptr(), logger.user_data) };
+    IN_CALLBACK.with(|in_callback| in_callback.set(false));
+}
+
+/// Replaces the callback, or removes it if `callback` is `None`. Waits for calls to the old
+/// callback on other threads to return first.
+pub fn set_callback(level: LogLevel, callback: LogCallback, user_data: *mut c_void) -> Result<(), Error> {
+    if IN_CALLBACK.with(Cell::get) {
+        return Err(Error::new(ErrorCode::InvalidArgument, "the log callback can't be changed from inside the log callback"));
+    }
+    let mut logger = LOGGER.write().unwrap_or_else(PoisonError::into_inner);
+    *logger = callback.map(|callback| Logger { level, callback, user_data });
+    MAX_LEVEL.store(if logger.is_some() { level as i32 } else { -1 }, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Registers `callback` to receive every message at `level` or more serious, one of the
+/// `LogLevel` values, along with `user_data`. A NULL `callback` removes the current one. Messages
+/// come from whichever thread is inside the library, possibly several at once, so the callback
+/// must be thread safe. It may call other functions of the library, whose own messages are then
+/// dropped, but not this one. Once this returns the previous callback is not running and won't be
+/// called again, so its `user_data` can be freed. Returns 0 or an `ErrorCode`.
+///
+/// # Safety
+///
+/// `callback` must be NULL or a function that is safe to call from any thread with `user_data`
+/// until it is replaced or removed.
+#[no_mangle]
+pub unsafe extern "C" fn file_checksum_set_log_callback(level: c_int, callback: LogCallback, user_data: *mut c_void) -> c_int {
+    error::guard_status(|| {
+        let level = LogLevel::from_raw(level).ok_or_else(|| Error::new(ErrorCode::InvalidArgument, format!("unsupported log level {}", level)))?;
+        set_callback(level, callback, user_data)
+    }) as c_int
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::ffi::CStr;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    use checksum::{self, Algorithm};
+    use get_checksum;
+
+    /// Messages seen, kept only if they mention `needle` since other tests log concurrently.
+    struct Collector {
+        needle: String,
+        messages: Mutex<Vec<(c_int, String, String)>>,
+    }
+
+    unsafe extern "C" fn collect(level: c_int, target: *const c_char, message: *const c_char, user_data: *mut c_void) {
+        let collector = &*(user_data as *const Collector);
+        let message = CStr::from_ptr(message).to_string_lossy().into_owned();
+        if message.contains(&collector.needle) {
+            let target = CStr::from_ptr(target).to_string_lossy().into_owned();
+            collector.messages.lock().unwrap().push((level, target, message));
+            // Calls back into the library are allowed, but can't log or change the callback
+            assert_eq!(file_checksum_set_log_callback(0, None, ::std::ptr::null_mut()), ErrorCode::InvalidArgument as c_int);
+        }
+    }
+
+    fn take(collector: &Collector) -> Vec<(c_int, String, String)> {
+        collector.messages.lock().unwrap().drain(..).collect()
+    }
+
+    #[test]
+    fn callback_receives_diagnostics() {
+        let dir = temp_dir().join("file_checksum_logging");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data");
+        fs::write(&path, b"abc").unwrap();
+        let collector = Collector { needle: "file_checksum_logging".to_string(), messages: Mutex::new(Vec::new()) };
+        let user_data = &collector as *const Collector as *mut c_void;
+
+        unsafe {
+            assert_eq!(file_checksum_set_log_callback(LogLevel::Debug as c_int, Some(collect), user_data), 0);
+            assert!(enabled(LogLevel::Debug) && !enabled(LogLevel::Trace));
+            checksum::checksum_file(&path, Algorithm::Sha256).unwrap();
+            let messages = take(&collector);
+            assert!(messages.iter().any(|m| m.0 == LogLevel::Debug as c_int && m.1 == "checksum" && m.2.contains("opening")), "{:?}", messages);
+
+            // Errors returned by exported calls are logged too
+            let missing = CString::new(dir.join("missing").to_str().unwrap()).unwrap();
+            assert!(get_checksum(missing.as_ptr()).is_null());
+            assert_eq!(error::last_error_code(), ErrorCode::NotFound);
+            let messages = take(&collector);
+            assert!(messages.iter().any(|m| m.0 == LogLevel::Error as c_int && m.1 == "error"), "{:?}", messages);
+
+            // Only errors get through at the error level
+            assert_eq!(file_checksum_set_log_callback(LogLevel::Error as c_int, Some(collect), user_data), 0);
+            checksum::checksum_file(&path, Algorithm::Sha256).unwrap();
+            assert!(take(&collector).is_empty());
+
+            assert_eq!(file_checksum_set_log_callback(LogLevel::Trace as c_int + 1, Some(collect), user_data), ErrorCode::InvalidArgument as c_int);
+            assert_eq!(file_checksum_set_log_callback(LogLevel::Trace as c_int, None, user_data), 0);
+            assert!(!enabled(LogLevel::Error));
+            log(LogLevel::Error, "test", format_args!("{}", Path::new("file_checksum_logging").display()));
+            assert!(take(&collector).is_empty());
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! Hashing whole directory trees into a manifest of relative path, size and digest entries. The
+//! entries are sorted by path so the same tree always produces the same manifest.
+
+use std::ffi::CString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use libc::{c_char, c_int};
+
+use cache;
+use checksum::{self, Algorithm};
+use error::{self, Error};
+use ffi::{algorithm_from_c, bytes_into_c_string, from_c_array, into_c_array, path_from_c};
+
+/// Controls which files `hash_directory` visits. The default hashes everything with SHA-256
+/// without following links.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ManifestOptions {
+    pub algorithm: Algorithm,
+    /// Follow symbolic links to files and directories. When false, links are skipped.
+    pub follow_symlinks: bool,
+    /// Skip files and directories whose name starts with a dot.
+    pub skip_hidden: bool,
+    /// How many levels of subdirectories to descend into, `Some(0)` only hashes the files directly
+    /// inside the root. `None` has no limit.
+    pub max_depth: Option<usize>,
+}
+
+/// One regular file in a manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Path of the file relative to the root that was hashed.
+    pub path: PathBuf,
+    pub size: u64,
+    pub digest: Vec<u8>,
+}
+
+/// Renders a relative path with `/` separators whatever the platform, so manifests made on
+/// different systems compare equal. On Unix the bytes of the name are kept as they are.
+pub fn path_bytes(path: &Path) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for (i, component) in path.components().enumerate() {
+        if i > 0 {
+            bytes.push(b'/');
+        }
+        bytes.extend_from_slice(&os_str_bytes(component.as_os_str()));
+    }
+    bytes
+}
+
+/// The inverse of `path_bytes`, turning the bytes of a `/` separated path back into a path.
+#[cfg(unix)]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(::std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// The bytes of a file name, exactly on Unix and as lossy UTF-8 elsewhere.
+#[cfg(unix)]
+pub fn os_str_bytes(value: &::std::ffi::OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    value.as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+pub fn os_str_bytes(value: &::std::ffi::OsStr) -> Vec<u8> {
+    value.to_string_lossy().into_owned().into_bytes()
+}
+
+fn is_hidden(name: &::std::ffi::OsStr) -> bool {
+    os_str_bytes(name).first() == Some(&b'.')
+}
+
+struct Walker<'a> {
+    options: &'a ManifestOptions,
+    /// Canonical paths of the directories being walked, used to break symlink loops.
+    ancestors: Vec<PathBuf>,
+    /// Absolute and relative paths of the regular files found.
+    files: Vec<(PathBuf, PathBuf)>,
+}
+
+impl<'a> Walker<'a> {
+    fn walk(&mut self, dir: &Path, relative: &Path, depth: usize) -> Result<(), Error> {
+        let entries = fs::read_dir(dir).map_err(|err| Error::from_io(&err, &dir.to_string_lossy()))?;
+        for entry in entries {
+            let entry = entry.map_err(|err| Error::from_io(&err, &dir.to_string_lossy()))?;
+            let name = entry.file_name();
+            if self.options.skip_hidden && is_hidden(&name) {
+                continue;
+            }
+            let path = entry.path();
+            let mut file_type = entry.file_type().map_err(|err| Error::from_io(&err, &path.to_string_lossy()))?;
+            if file_type.is_symlink() {
+                if !self.options.follow_symlinks {
+                    continue;
+                }
+                file_type = match fs::metadata(&path) {
+                    Ok(metadata) => metadata.file_type(),
+                    // A dangling link has nothing to hash
+                    Err(_) => continue,
+                };
+            }
+
+            let relative = relative.join(&name);
+            if file_type.is_file() {
+                self.files.push((path, relative));
+            } else if file_type.is_dir() && self.options.max_depth.is_none_or(|max| depth < max) {
+                let canonical = fs::canonicalize(&path).map_err(|err| Error::from_io(&err, &path.to_string_lossy()))?;
+                if self.ancestors.contains(&canonical) {
+                    continue;
+                }
+                self.ancestors.push(canonical);
+                self.walk(&path, &relative, depth + 1)?;
+                self.ancestors.pop();
+            }
+            // Sockets, pipes and devices are not part of a manifest
+        }
+        Ok(())
+    }
+}
+
+/// Returns the full and relative paths of the regular files under `root`, sorted by relative path.
+fn walk_directory(root: &Path, options: &ManifestOptions) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+    let canonical = fs::canonicalize(root).map_err(|err| Error::from_io(&err, &root.to_string_lossy()))?;
+    let mut walker = Walker { options, ancestors: vec![canonical], files: Vec::new() };
+    walker.walk(root, Path::new(""), 0)?;
+    let mut files = walker.files;
+    files.sort_by(|a, b| a.1.cmp(&b.1));
+    Ok(files)
+}
+
+/// Lists the regular files `hash_directory` would hash, as paths under `root` in the same order.
+/// The algorithm in `options` is ignored.
+pub fn find_files<P: AsRef<Path>>(root: P, options: &ManifestOptions) -> Result<Vec<PathBuf>, Error> {
+    Ok(walk_directory(root.as_ref(), options)?.into_iter().map(|(path, _)| path).collect())
+}
+
+/// Walks the directory tree under `root` and checksums every regular file in it, returning the
+/// entries sorted by relative path.
+pub fn hash_directory<P: AsRef<Path>>(root: P, options: &ManifestOptions) -> Result<Vec<ManifestEntry>, Error> {
+    walk_directory(root.as_ref(), options)?
+        .into_iter()
+        .map(|(path, relative)| {
+            let to_error = |err| Error::from_io(&err, &path.to_string_lossy());
+            let size = fs::metadata(&path).map_err(to_error)?.len();
+            let digest = cache::checksum_file(&path, options.algorithm, false).map_err(to_error)?;
+            Ok(ManifestEntry { path: relative, size, digest })
+        })
+        .collect()
+}
+
+/// Options for `get_directory_manifest`, see `ManifestOptions`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CManifestOptions {
+    /// One of the `Algorithm` values.
+    pub algorithm: c_int,
+    pub follow_symlinks: bool,
+    pub skip_hidden: bool,
+    /// Levels of subdirectories to descend into, or a negative value for no limit.
+    pub max_depth: c_int,
+}
+
+/// One file of a `CManifest`.
+#[repr(C)]
+pub struct CManifestEntry {
+    /// Path relative to the root with `/` separators.
+    pub path: *mut c_char,
+    pub size: u64,
+    /// Lowercase hex digest.
+    pub digest: *mut c_char,
+}
+
+/// The result of `get_directory_manifest`, released with `release_manifest`.
+#[repr(C)]
+pub struct CManifest {
+    pub entries: *mut CManifestEntry,
+    pub count: usize,
+}
+
+impl CManifestOptions {
+    pub fn to_options(self) -> Result<ManifestOptions, Error> {
+        Ok(ManifestOptions {
+            algorithm: algorithm_from_c(self.algorithm)?,
+            follow_symlinks: self.follow_symlinks,
+            skip_hidden: self.skip_hidden,
+            max_depth: if self.max_depth < 0 { None } else { Some(self.max_depth as usize) },
+        })
+    }
+}
+
+/// Converts entries into a `CManifest` for the exports that return one, to be freed with
+/// `release_manifest`.
+pub fn to_c_manifest(entries: Vec<ManifestEntry>) -> *mut CManifest {
+    let entries = entries
+        .into_iter()
+        .map(|entry| CManifestEntry {
+            path: bytes_into_c_string(path_bytes(&entry.path)),
+            size: entry.size,
+            digest: bytes_into_c_string(checksum::to_hex(&entry.digest).into_bytes()),
+        })
+        .collect();
+    let (entries, count) = into_c_array(entries);
+    Box::into_raw(Box::new(CManifest { entries, count }))
+}
+
+/// Checksums every regular file under the directory `dirpath` and returns the entries sorted by
+/// path, or NULL on failure. A NULL `options` hashes everything with SHA-256 without following
+/// links. The result must be freed with `release_manifest`.
+///
+/// # Safety
+///
+/// `dirpath` must be NULL or point to a valid NUL terminated string and `options` must be NULL or
+/// point to a valid `CManifestOptions`.
+#[no_mangle]
+pub unsafe extern "C" fn get_directory_manifest(dirpath: *const c_char, options: *const CManifestOptions) -> *mut CManifest {
+    error::guard(ptr::null_mut(), || {
+        let dirpath = path_from_c(dirpath)?;
+        let options = if options.is_null() { ManifestOptions::default() } else { (*options).to_options()? };
+        hash_directory(dirpath, &options).map(to_c_manifest)
+    })
+}
+
+/// Frees a manifest returned by `get_directory_manifest` or `get_archive_manifest`.
+///
+/// # Safety
+///
+/// `manifest` must be NULL or a pointer returned by `get_directory_manifest` or
+/// `get_archive_manifest` that has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn release_manifest(manifest: *mut CManifest) {
+    if manifest.is_null() {
+        return;
+    }
+    error::guard_silent((), || {
+        let manifest = Box::from_raw(manifest);
+        for entry in from_c_array(manifest.entries, manifest.count).iter() {
+            drop(CString::from_raw(entry.path));
+            drop(CString::from_raw(entry.digest));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::ffi::CStr;
+
+    use test_util::make_tree;
+
+    const TREE: [(&str, &[u8]); 4] = [("a.txt", b"a"), (".hidden", b"hidden"), ("sub/b.txt", b"bb"), ("sub/deeper/c.txt", b"ccc")];
+
+    fn paths(entries: &[ManifestEntry]) -> Vec<String> {
+        entries.iter().map(|e| String::from_utf8(path_bytes(&e.path)).unwrap()).collect()
+    }
+
+    #[test]
+    fn whole_tree() {
+        let root = make_tree(temp_dir().join("file_checksum_manifest_whole"), &TREE);
+        let entries = hash_directory(&root, &ManifestOptions::default()).unwrap();
+        assert_eq!(paths(&entries), vec![".hidden", "a.txt", "sub/b.txt", "sub/deeper/c.txt"]);
+        assert_eq!(entries[3].size, 3);
+        assert_eq!(entries[1].digest, checksum::checksum_file(root.join("a.txt"), Algorithm::Sha256).unwrap());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn hidden_and_depth() {
+        let root = make_tree(temp_dir().join("file_checksum_manifest_depth"), &TREE);
+        let options = ManifestOptions { skip_hidden: true, max_depth: Some(1), ..ManifestOptions::default() };
+        assert_eq!(paths(&hash_directory(&root, &options).unwrap()), vec!["a.txt", "sub/b.txt"]);
+        let options = ManifestOptions { max_depth: Some(0), ..ManifestOptions::default() };
+        assert_eq!(paths(&hash_directory(&root, &options).unwrap()), vec![".hidden", "a.txt"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks() {
+        use std::os::unix::fs::symlink;
+        let root = make_tree(temp_dir().join("file_checksum_manifest_symlinks"), &TREE);
+        symlink(root.join("a.txt"), root.join("link.txt")).unwrap();
+        symlink(&root, root.join("sub").join("loop")).unwrap();
+        symlink(root.join("missing"), root.join("dangling")).unwrap();
+
+        let entries = hash_directory(&root, &ManifestOptions::default()).unwrap();
+        assert_eq!(paths(&entries), vec![".hidden", "a.txt", "sub/b.txt", "sub/deeper/c.txt"]);
+
+        // sub/loop points at the root, which is already being walked, so it isn't followed at all
+        let options = ManifestOptions { follow_symlinks: true, skip_hidden: true, ..ManifestOptions::default() };
+        let entries = hash_directory(&root, &options).unwrap();
+        assert_eq!(paths(&entries), vec!["a.txt", "link.txt", "sub/b.txt", "sub/deeper/c.txt"]);
+        assert_eq!(entries[0].digest, entries[1].digest);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn missing_root() {
+        let err = hash_directory("/this/path/does/not/exist", &ManifestOptions::default()).unwrap_err();
+        assert_eq!(err.code, ::error::ErrorCode::NotFound);
+    }
+
+    #[test]
+    fn c_manifest() {
+        let root = make_tree(temp_dir().join("file_checksum_manifest_c"), &TREE);
+        let dirpath = CString::new(root.to_str().unwrap()).unwrap();
+        let options = CManifestOptions { algorithm: Algorithm::Crc32 as c_int, follow_symlinks: false, skip_hidden: true, max_depth: -1 };
+        unsafe {
+            let manifest = get_directory_manifest(dirpath.as_ptr(), &options);
+            assert!(!manifest.is_null());
+            let entries = ::std::slice::from_raw_parts((*manifest).entries, (*manifest).count);
+            let listed: Vec<_> = entries
+                .iter()
+                .map(|e| (CStr::from_ptr(e.path).to_str().unwrap(), e.size, CStr::from_ptr(e.digest).to_str().unwrap()))
+                .collect();
+            assert_eq!(
+                listed,
+                vec![("a.txt", 1, "e8b7be43"), ("sub/b.txt", 2, "b5ae1bae"), ("sub/deeper/c.txt", 3, "2fbba4ed")]
+            );
+            release_manifest(manifest);
+
+            let bad = CManifestOptions { algorithm: 99, ..options };
+            assert!(get_directory_manifest(dirpath.as_ptr(), &bad).is_null());
+            release_manifest(ptr::null_mut());
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+}
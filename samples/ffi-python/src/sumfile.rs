@@ -0,0 +1,522 @@
+//! Reading, writing and verifying checksum files in the formats produced by common tools:
+//!
+//! * GNU coreutils `sha256sum` and friends, `<hex>  <path>` or `<hex> *<path>` for binary mode
+//! * BSD `sha256` and `sha256sum --tag`, `SHA256 (<path>) = <hex>`
+//! * Simple File Verification, `<path> <CRC32>` with `;` comment lines
+//!
+//! Paths are kept as bytes so names that are not UTF-8 survive a round trip.
+
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::ptr;
+use libc::{c_char, c_int};
+
+use cache;
+use checksum::{self, Algorithm};
+use error::{self, Error, ErrorCode};
+use ffi::{bytes_into_c_string, from_c_array, into_c_array, malloc_c_bytes, path_from_c};
+use manifest::{self, CManifestOptions, ManifestEntry, ManifestOptions};
+
+/// Layout of a checksum file. The discriminants are part of the C ABI and must not change.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SumFormat {
+    Gnu = 0,
+    Bsd = 1,
+    Sfv = 2,
+}
+
+impl SumFormat {
+    pub fn from_raw(value: i32) -> Option<SumFormat> {
+        [SumFormat::Gnu, SumFormat::Bsd, SumFormat::Sfv].iter().cloned().find(|f| *f as i32 == value)
+    }
+}
+
+/// One line of a checksum file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SumEntry {
+    /// Path as written in the file, with any escaping removed.
+    pub path: Vec<u8>,
+    pub algorithm: Algorithm,
+    pub digest: Vec<u8>,
+}
+
+impl SumEntry {
+    pub fn from_manifest_entry(entry: &ManifestEntry, algorithm: Algorithm) -> SumEntry {
+        SumEntry { path: manifest::path_bytes(&entry.path), algorithm, digest: entry.digest.clone() }
+    }
+}
+
+/// Outcome of checking one entry, in the terms `sha256sum -c` reports. The discriminants are part
+/// of the C ABI and must not change.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyStatus {
+    Ok = 0,
+    Failed = 1,
+    Missing = 2,
+    /// The file exists but couldn't be read, e.g. for lack of permission.
+    Unreadable = 3,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyResult {
+    pub path: Vec<u8>,
+    pub status: VerifyStatus,
+}
+
+/// Guesses the algorithm of a checksum file from its name, e.g. SHA256SUMS or release.md5.
+pub fn algorithm_from_file_name(name: &str) -> Option<Algorithm> {
+    const PATTERNS: [(&str, Algorithm); 9] = [
+        ("sha512", Algorithm::Sha512),
+        ("sha256", Algorithm::Sha256),
+        ("sha1", Algorithm::Sha1),
+        ("md5", Algorithm::Md5),
+        ("blake2", Algorithm::Blake2b),
+        ("b2sum", Algorithm::Blake2b),
+        ("crc32", Algorithm::Crc32),
+        (".sfv", Algorithm::Crc32),
+        ("adler32", Algorithm::Adler32),
+    ];
+    let name = name.to_ascii_lowercase();
+    PATTERNS.iter().find(|p| name.contains(p.0)).map(|p| p.1)
+}
+
+/// The most likely algorithm for an unlabelled digest of `len` bytes.
+fn algorithm_from_digest_len(len: usize) -> Option<Algorithm> {
+    match len {
+        4 => Some(Algorithm::Crc32),
+        16 => Some(Algorithm::Md5),
+        20 => Some(Algorithm::Sha1),
+        32 => Some(Algorithm::Sha256),
+        64 => Some(Algorithm::Sha512),
+        _ => None,
+    }
+}
+
+fn from_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    fn nibble(c: u8) -> Option<u8> {
+        (c as char).to_digit(16).map(|d| d as u8)
+    }
+    if hex.is_empty() || !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    hex.chunks(2).map(|pair| Some(nibble(pair[0])? << 4 | nibble(pair[1])?)).collect()
+}
+
+/// Undoes the escaping GNU tools apply to names containing backslashes or line breaks.
+fn unescape(path: &[u8]) -> Option<Vec<u8>> {
+    let mut result = Vec::with_capacity(path.len());
+    let mut bytes = path.iter();
+    while let Some(&b) = bytes.next() {
+        if b != b'\\' {
+            result.push(b);
+            continue;
+        }
+        match bytes.next() {
+            Some(b'\\') => result.push(b'\\'),
+            Some(b'n') => result.push(b'\n'),
+            Some(b'r') => result.push(b'\r'),
+            _ => return None,
+        }
+    }
+    Some(result)
+}
+
+fn needs_escape(path: &[u8]) -> bool {
+    path.iter().any(|b| *b == b'\\' || *b == b'\n' || *b == b'\r')
+}
+
+fn escape(path: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(path.len());
+    for &b in path {
+        match b {
+            b'\\' => result.extend_from_slice(b"\\\\"),
+            b'\n' => result.extend_from_slice(b"\\n"),
+            b'\r' => result.extend_from_slice(b"\\r"),
+            _ => result.push(b),
+        }
+    }
+    result
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).rposition(|w| w == needle)
+}
+
+/// `SHA256 (path) = hex`
+fn parse_bsd(line: &[u8], escaped: bool) -> Option<SumEntry> {
+    let open = find(line, b" (")?;
+    let close = rfind(line, b") = ")?;
+    if close < open + 2 {
+        return None;
+    }
+    let algorithm = Algorithm::from_name(::std::str::from_utf8(&line[..open]).ok()?)?;
+    let digest = from_hex(&line[close + 4..])?;
+    if digest.len() != algorithm.digest_len() {
+        return None;
+    }
+    let path = &line[open + 2..close];
+    let path = if escaped { unescape(path)? } else { path.to_vec() };
+    Some(SumEntry { path, algorithm, digest })
+}
+
+/// `hex  path` or `hex *path`
+fn parse_gnu(line: &[u8], escaped: bool, hint: Option<Algorithm>) -> Option<SumEntry> {
+    let space = line.iter().position(|b| *b == b' ')?;
+    if line.len() < space + 3 || (line[space + 1] != b' ' && line[space + 1] != b'*') {
+        return None;
+    }
+    let digest = from_hex(&line[..space])?;
+    let algorithm = hint.or_else(|| algorithm_from_digest_len(digest.len()))?;
+    if digest.len() != algorithm.digest_len() {
+        return None;
+    }
+    let path = &line[space + 2..];
+    let path = if escaped { unescape(path)? } else { path.to_vec() };
+    Some(SumEntry { path, algorithm, digest })
+}
+
+/// `path CRC32`
+fn parse_sfv(line: &[u8]) -> Option<SumEntry> {
+    let space = line.iter().rposition(|b| *b == b' ')?;
+    let digest = from_hex(&line[space + 1..])?;
+    if space == 0 || digest.len() != 4 {
+        return None;
+    }
+    Some(SumEntry { path: line[..space].to_vec(), algorithm: Algorithm::Crc32, digest })
+}
+
+/// Parses the contents of a checksum file. When `format` is `None` each line may be in any of the
+/// formats. GNU lines don't name their algorithm so `hint` is used, or failing that the length of
+/// the digest, which picks SHA-512 over BLAKE2b and CRC32 over Adler-32.
+pub fn parse_sums(data: &[u8], format: Option<SumFormat>, hint: Option<Algorithm>) -> Result<Vec<SumEntry>, Error> {
+    let mut entries = Vec::new();
+    for (number, line) in data.split(|b| *b == b'\n').enumerate() {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() || line[0] == b';' {
+            continue;
+        }
+        let (escaped, unprefixed) = match line.strip_prefix(b"\\") {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let entry = match format {
+            Some(SumFormat::Gnu) => parse_gnu(unprefixed, escaped, hint),
+            Some(SumFormat::Bsd) => parse_bsd(unprefixed, escaped),
+            Some(SumFormat::Sfv) => parse_sfv(line),
+            None => parse_bsd(unprefixed, escaped)
+                .or_else(|| parse_gnu(unprefixed, escaped, hint))
+                .or_else(|| parse_sfv(line)),
+        };
+        match entry {
+            Some(entry) => entries.push(entry),
+            None => {
+                return Err(Error::new(
+                    ErrorCode::InvalidManifest,
+                    format!("line {} is not a valid checksum line", number + 1),
+                ))
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Writes entries as a checksum file. SFV can only hold CRC32 digests.
+pub fn format_sums(entries: &[SumEntry], format: SumFormat) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    for entry in entries {
+        let hex = checksum::to_hex(&entry.digest);
+        let escaped = needs_escape(&entry.path);
+        let path = if escaped { escape(&entry.path) } else { entry.path.clone() };
+        if escaped && format != SumFormat::Sfv {
+            out.push(b'\\');
+        }
+        match format {
+            SumFormat::Gnu => {
+                out.extend_from_slice(hex.as_bytes());
+                out.extend_from_slice(b"  ");
+                out.extend_from_slice(&path);
+            }
+            SumFormat::Bsd => {
+                out.extend_from_slice(entry.algorithm.name().as_bytes());
+                out.extend_from_slice(b" (");
+                out.extend_from_slice(&path);
+                out.extend_from_slice(b") = ");
+                out.extend_from_slice(hex.as_bytes());
+            }
+            SumFormat::Sfv => {
+                if entry.algorithm != Algorithm::Crc32 {
+                    return Err(Error::new(ErrorCode::UnsupportedAlgorithm, "SFV files can only hold CRC32 checksums"));
+                }
+                if escaped {
+                    return Err(Error::new(ErrorCode::InvalidManifest, "SFV files can't hold names with line breaks"));
+                }
+                out.extend_from_slice(&path);
+                out.push(b' ');
+                out.extend_from_slice(hex.to_ascii_uppercase().as_bytes());
+            }
+        }
+        out.push(b'\n');
+    }
+    Ok(out)
+}
+
+/// Checks one entry against the file it names. A relative path is resolved against `base_dir`.
+pub fn verify_entry(entry: &SumEntry, base_dir: &Path) -> VerifyResult {
+    let path = base_dir.join(manifest::path_from_bytes(&entry.path));
+    let status = match cache::checksum_file(&path, entry.algorithm, false) {
+        Ok(ref digest) if *digest == entry.digest => VerifyStatus::Ok,
+        Ok(_) => VerifyStatus::Failed,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => VerifyStatus::Missing,
+        Err(_) => VerifyStatus::Unreadable,
+    };
+    VerifyResult { path: entry.path.clone(), status }
+}
+
+/// Checks every entry against the file it names. Relative paths are resolved against `base_dir`.
+pub fn verify_entries(entries: &[SumEntry], base_dir: &Path) -> Vec<VerifyResult> {
+    entries.iter().map(|entry| verify_entry(entry, base_dir)).collect()
+}
+
+/// Reads the checksum file at `path` and checks every entry in it, like `sha256sum -c`. Relative
+/// paths inside it are resolved against the directory the checksum file is in. If `hint` is
+/// `None` the algorithm of GNU lines is guessed from the file name and then the digest length.
+pub fn verify_sum_file<P: AsRef<Path>>(path: P, hint: Option<Algorithm>) -> Result<Vec<VerifyResult>, Error> {
+    let path = path.as_ref();
+    let data = fs::read(path).map_err(|err| Error::from_io(&err, &path.to_string_lossy()))?;
+    let hint = hint.or_else(|| path.file_name().and_then(|name| algorithm_from_file_name(&name.to_string_lossy())));
+    let entries = parse_sums(&data, None, hint)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    Ok(verify_entries(&entries, base_dir))
+}
+
+/// One line of a `CVerifyReport`.
+#[repr(C)]
+pub struct CVerifyEntry {
+    /// The path as written in the checksum file.
+    pub path: *mut c_char,
+    pub status: VerifyStatus,
+}
+
+/// The result of `verify_manifest`, released with `release_verify_report`.
+#[repr(C)]
+pub struct CVerifyReport {
+    pub entries: *mut CVerifyEntry,
+    pub count: usize,
+}
+
+fn to_c_report(results: Vec<VerifyResult>) -> *mut CVerifyReport {
+    let entries = results
+        .into_iter()
+        .map(|result| CVerifyEntry { path: bytes_into_c_string(result.path), status: result.status })
+        .collect();
+    let (entries, count) = into_c_array(entries);
+    Box::into_raw(Box::new(CVerifyReport { entries, count }))
+}
+
+/// Checks every entry of the GNU, BSD or SFV checksum file at `path` and reports OK, FAILED,
+/// MISSING or UNREADABLE for each, or returns NULL if the checksum file can't be read or parsed.
+/// The result must be freed with `release_verify_report`.
+///
+/// # Safety
+///
+/// `path` must be NULL or point to a valid NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn verify_manifest(path: *const c_char) -> *mut CVerifyReport {
+    error::guard(ptr::null_mut(), || verify_sum_file(path_from_c(path)?, None).map(to_c_report))
+}
+
+/// Frees a report returned by `verify_manifest`.
+///
+/// # Safety
+///
+/// `report` must be NULL or a pointer returned by `verify_manifest` that has not been released
+/// yet.
+#[no_mangle]
+pub unsafe extern "C" fn release_verify_report(report: *mut CVerifyReport) {
+    if report.is_null() {
+        return;
+    }
+    error::guard_silent((), || {
+        let report = Box::from_raw(report);
+        for entry in from_c_array(report.entries, report.count).iter() {
+            drop(CString::from_raw(entry.path));
+        }
+    })
+}
+
+/// Hashes the directory `dirpath` as `get_directory_manifest` does and returns it as the text of
+/// a checksum file in `format`, one of the `SumFormat` values. Returns NULL on failure. The result
+/// must be freed with `release_checksum`.
+///
+/// # Safety
+///
+/// `dirpath` must be NULL or point to a valid NUL terminated string and `options` must be NULL or
+/// point to a valid `CManifestOptions`.
+#[no_mangle]
+pub unsafe extern "C" fn format_directory_manifest(dirpath: *const c_char, options: *const CManifestOptions, format: c_int) -> *mut c_char {
+    error::guard(ptr::null_mut(), || {
+        let dirpath = path_from_c(dirpath)?;
+        let format = SumFormat::from_raw(format)
+            .ok_or_else(|| Error::new(ErrorCode::InvalidArgument, format!("unsupported format {}", format)))?;
+        let options = if options.is_null() { ManifestOptions::default() } else { (*options).to_options()? };
+        let entries: Vec<SumEntry> = manifest::hash_directory(dirpath, &options)?
+            .iter()
+            .map(|entry| SumEntry::from_manifest_entry(entry, options.algorithm))
+            .collect();
+        // Names that aren't UTF-8 are passed through as they are, C strings are just bytes
+        malloc_c_bytes(&format_sums(&entries, format)?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::ffi::CStr;
+    use std::path::PathBuf;
+
+    use test_util::make_tree;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    const ABC_MD5: &str = "900150983cd24fb0d6963f7d28e17f72";
+
+    fn entry(path: &str, algorithm: Algorithm, hex: &str) -> SumEntry {
+        SumEntry { path: path.as_bytes().to_vec(), algorithm, digest: from_hex(hex.as_bytes()).unwrap() }
+    }
+
+    #[test]
+    fn parse_gnu_lines() {
+        let text = format!("{}  abc.txt\n{} *bin/abc.dat\r\n\\{}  back\\\\slash\\nnewline\n", ABC_SHA256, ABC_SHA256, ABC_SHA256);
+        let entries = parse_sums(text.as_bytes(), Some(SumFormat::Gnu), None).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                entry("abc.txt", Algorithm::Sha256, ABC_SHA256),
+                entry("bin/abc.dat", Algorithm::Sha256, ABC_SHA256),
+                entry("back\\slash\nnewline", Algorithm::Sha256, ABC_SHA256),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_gnu_with_hint() {
+        let text = format!("{}  abc.txt\n", ABC_MD5);
+        assert_eq!(parse_sums(text.as_bytes(), None, None).unwrap()[0].algorithm, Algorithm::Md5);
+        // The hint wins over the length, but must agree with it
+        let crc = "352441c2  abc.txt\n";
+        assert_eq!(parse_sums(crc.as_bytes(), None, Some(Algorithm::Adler32)).unwrap()[0].algorithm, Algorithm::Adler32);
+        assert_eq!(
+            parse_sums(text.as_bytes(), Some(SumFormat::Gnu), Some(Algorithm::Sha1)).unwrap_err().code,
+            ErrorCode::InvalidManifest
+        );
+    }
+
+    #[test]
+    fn parse_bsd_and_sfv_lines() {
+        let text = format!("MD5 (abc (1).txt) = {}\n; comment\nabc file.txt 352441C2\n", ABC_MD5);
+        let entries = parse_sums(text.as_bytes(), None, None).unwrap();
+        assert_eq!(
+            entries,
+            vec![entry("abc (1).txt", Algorithm::Md5, ABC_MD5), entry("abc file.txt", Algorithm::Crc32, "352441c2")]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = parse_sums(b"ok\n", None, None).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidManifest);
+        assert_eq!(err.message, "line 1 is not a valid checksum line");
+        assert!(parse_sums(b"SHA3 (x) = 00\n", Some(SumFormat::Bsd), None).is_err());
+        assert!(parse_sums(b"abc  x\n", Some(SumFormat::Gnu), None).is_err());
+    }
+
+    #[test]
+    fn round_trips() {
+        let entries = vec![
+            entry("a.txt", Algorithm::Crc32, "352441c2"),
+            entry("dir/odd\\name\n", Algorithm::Crc32, "00000001"),
+        ];
+        for format in [SumFormat::Gnu, SumFormat::Bsd].iter() {
+            let text = format_sums(&entries, *format).unwrap();
+            assert_eq!(parse_sums(&text, Some(*format), Some(Algorithm::Crc32)).unwrap(), entries);
+        }
+        assert_eq!(format_sums(&entries[..1], SumFormat::Gnu).unwrap(), b"352441c2  a.txt\n");
+        assert_eq!(format_sums(&entries[..1], SumFormat::Bsd).unwrap(), b"CRC32 (a.txt) = 352441c2\n");
+        assert_eq!(format_sums(&entries[..1], SumFormat::Sfv).unwrap(), b"a.txt 352441C2\n");
+        assert!(format_sums(&entries, SumFormat::Sfv).is_err());
+        assert!(format_sums(&[entry("a", Algorithm::Md5, ABC_MD5)], SumFormat::Sfv).is_err());
+    }
+
+    #[test]
+    fn file_name_hints() {
+        assert_eq!(algorithm_from_file_name("SHA256SUMS"), Some(Algorithm::Sha256));
+        assert_eq!(algorithm_from_file_name("release.md5"), Some(Algorithm::Md5));
+        assert_eq!(algorithm_from_file_name("B2SUMS"), Some(Algorithm::Blake2b));
+        assert_eq!(algorithm_from_file_name("disk1.sfv"), Some(Algorithm::Crc32));
+        assert_eq!(algorithm_from_file_name("CHECKSUMS"), None);
+    }
+
+    fn make_verify_dir(name: &str) -> PathBuf {
+        let sums = format!("{0}  good.txt\n{0}  bad.txt\n{0}  missing.txt\n", ABC_SHA256);
+        make_tree(temp_dir().join(name), &[("good.txt", b"abc"), ("bad.txt", b"abd"), ("SHA256SUMS", sums.as_bytes())])
+    }
+
+    #[test]
+    fn verify() {
+        let dir = make_verify_dir("file_checksum_verify");
+        let results = verify_sum_file(dir.join("SHA256SUMS"), None).unwrap();
+        let statuses: Vec<_> = results.iter().map(|r| (String::from_utf8(r.path.clone()).unwrap(), r.status)).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("good.txt".to_string(), VerifyStatus::Ok),
+                ("bad.txt".to_string(), VerifyStatus::Failed),
+                ("missing.txt".to_string(), VerifyStatus::Missing),
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn c_verify_and_format() {
+        let dir = make_verify_dir("file_checksum_c_verify");
+        let sums = CString::new(dir.join("SHA256SUMS").to_str().unwrap()).unwrap();
+        unsafe {
+            let report = verify_manifest(sums.as_ptr());
+            assert!(!report.is_null());
+            let entries = ::std::slice::from_raw_parts((*report).entries, (*report).count);
+            assert_eq!(CStr::from_ptr(entries[1].path).to_str().unwrap(), "bad.txt");
+            let statuses: Vec<_> = entries.iter().map(|e| e.status).collect();
+            assert_eq!(statuses, vec![VerifyStatus::Ok, VerifyStatus::Failed, VerifyStatus::Missing]);
+            release_verify_report(report);
+
+            let missing = CString::new(dir.join("nope").to_str().unwrap()).unwrap();
+            assert!(verify_manifest(missing.as_ptr()).is_null());
+            assert_eq!(error::last_error_code(), ErrorCode::NotFound);
+
+            fs::remove_file(dir.join("SHA256SUMS")).unwrap();
+            let dirpath = CString::new(dir.to_str().unwrap()).unwrap();
+            let text = format_directory_manifest(dirpath.as_ptr(), ptr::null(), SumFormat::Bsd as c_int);
+            assert_eq!(
+                CStr::from_ptr(text).to_str().unwrap(),
+                format!(
+                    "SHA256 (bad.txt) = {}\nSHA256 (good.txt) = {}\n",
+                    checksum::to_hex(&checksum::checksum_file(dir.join("bad.txt"), Algorithm::Sha256).unwrap()),
+                    ABC_SHA256
+                )
+            );
+            ::release_checksum(text);
+            assert!(format_directory_manifest(dirpath.as_ptr(), ptr::null(), 9).is_null());
+            assert_eq!(error::last_error_code(), ErrorCode::InvalidArgument);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,28 @@
+//! Fixtures shared by the unit tests of several modules.
+
+use std::fs;
+use std::path::PathBuf;
+
+/// Data without long repeats, so blocks and chunks only match where they're meant to.
+pub fn pseudo_random(len: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+/// Replaces whatever is at `root` with a tree holding `files`, given as paths relative to it with
+/// their contents, and returns `root`. Parent directories are created as needed.
+pub fn make_tree(root: PathBuf, files: &[(&str, &[u8])]) -> PathBuf {
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    for &(path, data) in files {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, data).unwrap();
+    }
+    root
+}
@@ -0,0 +1,152 @@
+//! Guards the C interface. Every function, type and constant in the generated header must match
+//! the declaration recorded in tests/abi/exports.txt and the built library must export every
+//! recorded function, so a function that disappears or changes signature, a struct whose layout
+//! changes or an enum value that moves fails here. That is the cue to bump `ABI_VERSION` before
+//! recording the new declaration; something new only needs recording.
+
+extern crate file_checksum;
+extern crate libc;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use file_checksum::ABI_VERSION;
+
+fn manifest_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+/// Collapses the whitespace cbindgen uses to lay out declarations.
+fn normalize(declaration: &str) -> String {
+    declaration.split_whitespace().collect::<Vec<_>>().join(" ").replace("( ", "(")
+}
+
+/// The name a declaration declares: the function of a prototype, the type of a typedef or the
+/// macro of a define.
+fn declared_name(declaration: &str) -> String {
+    if declaration.starts_with("#define") {
+        declaration.split_whitespace().nth(1).unwrap().to_string()
+    } else if let Some(start) = declaration.find("(*").filter(|_| declaration.starts_with("typedef")) {
+        let name = &declaration[start + 2..];
+        name[..name.find(')').unwrap()].to_string()
+    } else if declaration.starts_with("typedef") {
+        declaration.trim_end_matches(';').rsplit(' ').next().unwrap().to_string()
+    } else {
+        let before_args = &declaration[..declaration.find('(').expect("not a prototype")];
+        before_args.rsplit([' ', '*']).next().unwrap().to_string()
+    }
+}
+
+fn is_function(declaration: &str) -> bool {
+    !declaration.starts_with("typedef") && !declaration.starts_with("#define")
+}
+
+/// The functions, types and constants in the generated header, by name, without comments. The
+/// include guard and `FILE_CHECKSUM_ABI_VERSION` itself are left out.
+fn header_declarations() -> BTreeMap<String, String> {
+    let header = fs::read_to_string(manifest_dir().join("include").join("file_checksum.h")).unwrap();
+    let mut declarations = Vec::new();
+    let mut code = String::new();
+    let mut in_cplusplus = false;
+    for line in header.lines() {
+        let line = line.split("//").next().unwrap().trim();
+        if line.starts_with("#ifdef __cplusplus") {
+            in_cplusplus = true;
+        } else if in_cplusplus {
+            in_cplusplus = !line.starts_with("#endif");
+        } else if line.starts_with("#define") {
+            declarations.push(normalize(line));
+        } else if !line.starts_with('#') && !line.starts_with("/*") {
+            code.push_str(line);
+            code.push(' ');
+        }
+    }
+    // Split at the semicolons outside struct and enum bodies
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in code.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ';' if depth == 0 => {
+                declarations.push(normalize(&code[start..=i]));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    declarations
+        .into_iter()
+        .map(|d| (declared_name(&d), d))
+        .filter(|(name, _)| name != "FILE_CHECKSUM_H" && name != "FILE_CHECKSUM_ABI_VERSION")
+        .collect()
+}
+
+/// The declarations recorded as the current ABI, by name.
+fn recorded_declarations() -> BTreeMap<String, String> {
+    fs::read_to_string(manifest_dir().join("tests").join("abi").join("exports.txt"))
+        .unwrap()
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with("//"))
+        .map(|line| (declared_name(line), normalize(line)))
+        .collect()
+}
+
+#[test]
+fn header_matches_recorded_abi() {
+    let header = header_declarations();
+    let recorded = recorded_declarations();
+    let mut problems = Vec::new();
+    for (name, declaration) in recorded.iter() {
+        match header.get(name) {
+            None => problems.push(format!("{} was removed", name)),
+            Some(current) if current != declaration => problems.push(format!("{} changed from `{}` to `{}`", name, declaration, current)),
+            Some(_) => {}
+        }
+    }
+    for name in header.keys().filter(|name| !recorded.contains_key(*name)) {
+        problems.push(format!("{} is new and needs adding to tests/abi/exports.txt", name));
+    }
+    assert!(problems.is_empty(), "the C interface changed, bump ABI_VERSION if anything was removed or changed:\n{}", problems.join("\n"));
+
+    let header = fs::read_to_string(manifest_dir().join("include").join("file_checksum.h")).unwrap();
+    assert!(header.contains(&format!("#define FILE_CHECKSUM_ABI_VERSION {}\n", ABI_VERSION)));
+}
+
+/// The directory holding the built libraries, which cargo puts next to the test executables.
+#[cfg(unix)]
+fn library_dir() -> PathBuf {
+    let deps = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let name = format!("{}file_checksum{}", env::consts::DLL_PREFIX, env::consts::DLL_SUFFIX);
+    if deps.join(&name).exists() {
+        deps
+    } else {
+        deps.parent().unwrap().to_path_buf()
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn library_exports_recorded_symbols() {
+    use std::ffi::{CStr, CString};
+    use std::mem;
+
+    let dir = library_dir();
+    assert!(dir.join("libfile_checksum.a").exists(), "no static library in {}", dir.display());
+    let path = CString::new(dir.join(format!("libfile_checksum{}", env::consts::DLL_SUFFIX)).to_str().unwrap()).unwrap();
+    unsafe {
+        let library = libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+        assert!(!library.is_null(), "{}", CStr::from_ptr(libc::dlerror()).to_string_lossy());
+        for name in recorded_declarations().iter().filter(|(_, d)| is_function(d)).map(|(name, _)| name) {
+            let symbol = CString::new(name.as_str()).unwrap();
+            assert!(!libc::dlsym(library, symbol.as_ptr()).is_null(), "{} is not exported", name);
+        }
+
+        let symbol = CString::new("file_checksum_abi_version").unwrap();
+        let abi_version: extern "C" fn() -> u32 = mem::transmute(libc::dlsym(library, symbol.as_ptr()));
+        assert_eq!(abi_version(), ABI_VERSION);
+        libc::dlclose(library);
+    }
+}
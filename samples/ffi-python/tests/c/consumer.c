@@ -0,0 +1,90 @@
+/* Prints the digest of a file with every supported algorithm using only the C API. Each line is
+ * "<name> <hex>" so the Rust side can compare them with its own digests. */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "file_checksum.h"
+
+static int fail(const char *what) {
+    const char *message = file_checksum_last_error_message();
+    fprintf(stderr, "%s failed: %d %s\n", what, file_checksum_last_error_code(), message ? message : "");
+    return 1;
+}
+
+int main(int argc, char **argv) {
+    size_t count, i, written = 0;
+    FileChecksumAlgorithmInfo *infos;
+    char buffer[65];
+    ChecksumHandle *handle;
+    char *checksum;
+    FILE *file;
+    size_t read;
+    uint8_t chunk[7];
+
+    if (argc != 2) {
+        fprintf(stderr, "usage: %s <file>\n", argv[0]);
+        return 2;
+    }
+
+    count = get_supported_algorithms(NULL, 0);
+    infos = (FileChecksumAlgorithmInfo *) calloc(count, sizeof(FileChecksumAlgorithmInfo));
+    get_supported_algorithms(infos, count);
+    for (i = 0; i < count; i++) {
+        checksum = get_checksum_with_algorithm(argv[1], infos[i].algorithm);
+        if (!checksum) {
+            return fail("get_checksum_with_algorithm");
+        }
+        if (strlen(checksum) != infos[i].digest_len * 2) {
+            fprintf(stderr, "%s digest has the wrong length\n", infos[i].name);
+            return 1;
+        }
+        printf("%s %s\n", infos[i].name, checksum);
+        release_checksum(checksum);
+    }
+    free(infos);
+
+    checksum = get_checksum(argv[1]);
+    if (!checksum) {
+        return fail("get_checksum");
+    }
+    printf("default %s\n", checksum);
+    release_checksum(checksum);
+
+    if (get_checksum_into(argv[1], NULL, 0, &written) != FILE_CHECKSUM_ERROR_BUFFER_TOO_SMALL || written != 64) {
+        return fail("get_checksum_into size query");
+    }
+    if (get_checksum_into(argv[1], buffer, sizeof(buffer), &written) != FILE_CHECKSUM_ERROR_OK) {
+        return fail("get_checksum_into");
+    }
+    printf("into %s\n", buffer);
+
+    /* Feed the file through a handle in small uneven chunks */
+    handle = checksum_new(FILE_CHECKSUM_ALGORITHM_SHA256);
+    if (!handle) {
+        return fail("checksum_new");
+    }
+    file = fopen(argv[1], "rb");
+    while ((read = fread(chunk, 1, sizeof(chunk), file)) > 0) {
+        if (checksum_update(handle, chunk, read) != FILE_CHECKSUM_ERROR_OK) {
+            return fail("checksum_update");
+        }
+    }
+    fclose(file);
+    checksum = checksum_finalize(handle);
+    if (!checksum) {
+        return fail("checksum_finalize");
+    }
+    printf("incremental %s\n", checksum);
+    release_checksum(checksum);
+    if (checksum_free(handle) != FILE_CHECKSUM_ERROR_OK || checksum_free(handle) != FILE_CHECKSUM_ERROR_INVALID_HANDLE) {
+        return fail("checksum_free");
+    }
+
+    if (get_checksum("/this/path/does/not/exist") != NULL) {
+        return 1;
+    }
+    printf("missing %d\n", file_checksum_last_error_code());
+    return 0;
+}
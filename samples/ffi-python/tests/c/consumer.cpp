@@ -0,0 +1,43 @@
+// Prints the digest of a file with every supported algorithm through the RAII wrapper in
+// file_checksum.hpp, in the same "<name> <hex>" form as consumer.c.
+
+#include <fstream>
+#include <iostream>
+
+#include "file_checksum.hpp"
+
+int main(int argc, char **argv) {
+    if (argc != 2) {
+        std::cerr << "usage: " << argv[0] << " <file>" << std::endl;
+        return 2;
+    }
+
+    try {
+        for (const auto &info : file_checksum::supported_algorithms()) {
+            auto algorithm = static_cast<FileChecksumAlgorithm>(info.algorithm);
+            std::cout << info.name << " " << file_checksum::checksum(argv[1], algorithm) << std::endl;
+        }
+        std::cout << "default " << file_checksum::checksum(argv[1]) << std::endl;
+
+        file_checksum::Hasher hasher;
+        std::ifstream file(argv[1], std::ios::binary);
+        char chunk[7];
+        while (file.read(chunk, sizeof(chunk)) || file.gcount() > 0) {
+            hasher.update(chunk, static_cast<size_t>(file.gcount()));
+        }
+        // Moving the hasher must hand over the handle rather than free it
+        file_checksum::Hasher moved(std::move(hasher));
+        std::cout << "incremental " << moved.finalize() << std::endl;
+    } catch (const file_checksum::Error &e) {
+        std::cerr << "error " << e.code() << ": " << e.what() << std::endl;
+        return 1;
+    }
+
+    try {
+        file_checksum::checksum("/this/path/does/not/exist");
+        return 1;
+    } catch (const file_checksum::Error &e) {
+        std::cout << "missing " << e.code() << std::endl;
+    }
+    return 0;
+}
@@ -0,0 +1,104 @@
+//! Compiles the programs in tests/c against the generated header and the built library with the
+//! system C and C++ compilers, runs them and checks their digests against the Rust API.
+
+extern crate file_checksum;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use file_checksum::checksum::{self, Algorithm};
+use file_checksum::error::ErrorCode;
+
+/// The directory holding libfile_checksum, which cargo builds next to the test executables.
+fn library_dir() -> PathBuf {
+    let deps = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let name = format!("{}file_checksum{}", env::consts::DLL_PREFIX, env::consts::DLL_SUFFIX);
+    if deps.join(&name).exists() {
+        deps
+    } else {
+        deps.parent().unwrap().to_path_buf()
+    }
+}
+
+fn manifest_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn compile(compiler_var: &str, default_compiler: &str, source: &str, extra_args: &[&str]) -> PathBuf {
+    let compiler = env::var(compiler_var).unwrap_or_else(|_| default_compiler.to_string());
+    let output = Path::new(env!("CARGO_TARGET_TMPDIR")).join(source.replace('.', "_"));
+    let lib_dir = library_dir();
+    let status = Command::new(&compiler)
+        .args(extra_args)
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(manifest_dir().join("include"))
+        .arg(manifest_dir().join("tests").join("c").join(source))
+        .arg("-o")
+        .arg(&output)
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-lfile_checksum")
+        .status()
+        .unwrap_or_else(|err| panic!("could not run {}: {}", compiler, err));
+    assert!(status.success(), "{} failed to compile {}", compiler, source);
+    output
+}
+
+/// Runs a consumer program on `path` and returns its "<name> <hex>" lines as a map.
+fn run(program: &Path, path: &Path) -> HashMap<String, String> {
+    let output = Command::new(program).arg(path).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{} failed: {}",
+        program.display(),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let mut parts = line.splitn(2, ' ');
+            (parts.next().unwrap().to_string(), parts.next().unwrap().to_string())
+        })
+        .collect()
+}
+
+fn test_file(name: &str) -> PathBuf {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let data: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 256) as u8).collect();
+    fs::write(&path, data).unwrap();
+    path
+}
+
+fn check_results(results: &HashMap<String, String>, path: &Path) {
+    for algorithm in Algorithm::ALL.iter() {
+        let expected = checksum::to_hex(&checksum::checksum_file(path, *algorithm).unwrap());
+        assert_eq!(results[algorithm.name()], expected, "{} digest differs", algorithm.name());
+    }
+    let sha256 = checksum::to_hex(&checksum::checksum_file(path, Algorithm::Sha256).unwrap());
+    assert_eq!(results["default"], sha256);
+    assert_eq!(results["incremental"], sha256);
+    assert_eq!(results["missing"], (ErrorCode::NotFound as i32).to_string());
+}
+
+#[test]
+fn c_consumer() {
+    let program = compile("CC", "cc", "consumer.c", &["-std=c99"]);
+    let path = test_file("c_consumer.bin");
+    let results = run(&program, &path);
+    check_results(&results, &path);
+    assert_eq!(results["into"], results["default"]);
+}
+
+#[test]
+fn cpp_consumer() {
+    let program = compile("CXX", "c++", "consumer.cpp", &["-std=c++11"]);
+    let path = test_file("cpp_consumer.bin");
+    check_results(&run(&program, &path), &path);
+}
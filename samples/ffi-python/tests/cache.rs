@@ -0,0 +1,121 @@
+//! Drives the process wide digest cache through the C API. It lives in its own test binary since
+//! every path based call in the process goes through the cache while it is open.
+
+extern crate file_checksum;
+extern crate libc;
+
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime};
+use libc::{c_char, c_int, c_void};
+
+use file_checksum::cache::{file_checksum_cache_clear, file_checksum_cache_close, file_checksum_cache_flush, file_checksum_cache_invalidate, file_checksum_cache_open};
+use file_checksum::checksum::Algorithm;
+use file_checksum::error::ErrorCode;
+use file_checksum::logging::{file_checksum_set_log_callback, LogLevel};
+use file_checksum::{get_checksum_cached, release_checksum};
+
+/// The tests share the process wide cache, so they take turns.
+static CACHE_TESTS: Mutex<()> = Mutex::new(());
+
+fn serialize() -> MutexGuard<'static, ()> {
+    CACHE_TESTS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Writes a file whose modification time is far enough in the past for it to be cached.
+fn write_old(path: &Path, data: &[u8], modified: SystemTime) {
+    fs::write(path, data).unwrap();
+    fs::OpenOptions::new().write(true).open(path).unwrap().set_modified(modified).unwrap();
+}
+
+unsafe fn md5(file: &CString, force: bool) -> String {
+    let digest = get_checksum_cached(file.as_ptr(), Algorithm::Md5 as c_int, force);
+    assert!(!digest.is_null());
+    let hex = CStr::from_ptr(digest).to_str().unwrap().to_string();
+    release_checksum(digest);
+    hex
+}
+
+#[test]
+fn c_api() {
+    let _serial = serialize();
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("cache_c_api");
+    fs::create_dir_all(&dir).unwrap();
+    let cache_path = dir.join("cache.bin");
+    let _ = fs::remove_file(&cache_path);
+    let file = dir.join("data.txt");
+    let modified = SystemTime::now() - Duration::from_secs(60);
+    write_old(&file, b"abc", modified);
+    let c_cache = CString::new(cache_path.to_str().unwrap()).unwrap();
+    let c_file = CString::new(file.to_str().unwrap()).unwrap();
+    let abc = "900150983cd24fb0d6963f7d28e17f72";
+
+    unsafe {
+        assert_eq!(file_checksum_cache_flush(), ErrorCode::InvalidArgument as c_int);
+        assert_eq!(file_checksum_cache_open(c_cache.as_ptr(), 0), 0);
+        assert_eq!(md5(&c_file, false), abc);
+        assert_eq!(file_checksum_cache_close(), 0);
+        assert!(cache_path.exists());
+
+        // Same size and mtime but different content, so only the reloaded cache gives the old digest
+        write_old(&file, b"xyz", modified);
+        assert_eq!(file_checksum_cache_open(c_cache.as_ptr(), 0), 0);
+        assert_eq!(md5(&c_file, false), abc);
+        assert_eq!(file_checksum_cache_invalidate(c_file.as_ptr()), 0);
+        let xyz = md5(&c_file, false);
+        assert_ne!(xyz, abc);
+
+        write_old(&file, b"abc", modified);
+        assert_eq!(md5(&c_file, false), xyz);
+        assert_eq!(md5(&c_file, true), abc);
+        write_old(&file, b"xyz", modified);
+        assert_eq!(file_checksum_cache_clear(), 0);
+        assert_eq!(md5(&c_file, false), xyz);
+        assert_eq!(file_checksum_cache_close(), 0);
+        assert_eq!(file_checksum_cache_close(), 0);
+    }
+}
+
+/// The file the log callback hashes, and how many times it has been called.
+struct CallbackState {
+    file: CString,
+    calls: AtomicUsize,
+}
+
+unsafe extern "C" fn use_cache_from_callback(_level: c_int, target: *const c_char, message: *const c_char, user_data: *mut c_void) {
+    let message = CStr::from_ptr(message).to_bytes();
+    if CStr::from_ptr(target).to_bytes() == b"cache" && (message.starts_with(b"hit") || message.starts_with(b"miss")) {
+        let state = &*(user_data as *const CallbackState);
+        state.calls.fetch_add(1, Ordering::SeqCst);
+        md5(&state.file, false);
+        assert_eq!(file_checksum_cache_flush(), 0);
+    }
+}
+
+#[test]
+fn log_callback_can_use_the_cache() {
+    let _serial = serialize();
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("cache_log_callback");
+    fs::create_dir_all(&dir).unwrap();
+    let cache_path = dir.join("cache.bin");
+    let _ = fs::remove_file(&cache_path);
+    let file = dir.join("data.txt");
+    write_old(&file, b"abc", SystemTime::now() - Duration::from_secs(60));
+    let c_cache = CString::new(cache_path.to_str().unwrap()).unwrap();
+    let state = CallbackState { file: CString::new(file.to_str().unwrap()).unwrap(), calls: AtomicUsize::new(0) };
+
+    unsafe {
+        let user_data = &state as *const CallbackState as *mut c_void;
+        assert_eq!(file_checksum_set_log_callback(LogLevel::Debug as c_int, Some(use_cache_from_callback), user_data), 0);
+        assert_eq!(file_checksum_cache_open(c_cache.as_ptr(), 0), 0);
+        // A miss that is stored and then a hit, both logged while the callback uses the cache
+        assert_eq!(md5(&state.file, false), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(md5(&state.file, false), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(file_checksum_set_log_callback(LogLevel::Debug as c_int, None, user_data), 0);
+        assert_eq!(file_checksum_cache_close(), 0);
+    }
+    assert_eq!(state.calls.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,132 @@
+//! Runs the file-checksum binary and checks its output against the library it's built on.
+
+extern crate file_checksum;
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use file_checksum::checksum::{self, Algorithm};
+
+fn run(args: &[&str], dir: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_file-checksum")).args(args).current_dir(dir).output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn make_tree(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("tree").join("sub")).unwrap();
+    fs::write(dir.join("tree").join("a.txt"), b"abc").unwrap();
+    fs::write(dir.join("tree").join("sub").join("b.txt"), b"hello\n").unwrap();
+    dir
+}
+
+fn hex(path: &Path, algorithm: Algorithm) -> String {
+    checksum::to_hex(&checksum::checksum_file(path, algorithm).unwrap())
+}
+
+#[test]
+fn hashes_files_and_directories() {
+    let dir = make_tree("cli_hash");
+    let a = dir.join("tree/a.txt");
+    let b = dir.join("tree/sub/b.txt");
+
+    let output = run(&["-r", "-j", "2", "tree"], &dir);
+    assert!(output.status.success());
+    assert_eq!(
+        stdout(&output),
+        format!("{}  tree/a.txt\n{}  tree/sub/b.txt\n", hex(&a, Algorithm::Sha256), hex(&b, Algorithm::Sha256))
+    );
+
+    let output = run(&["--algorithm=md5", "-a", "crc32,blake2b", "tree/a.txt"], &dir);
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "MD5 (tree/a.txt) = {}\nCRC32 (tree/a.txt) = {}\nBLAKE2b (tree/a.txt) = {}\n",
+            hex(&a, Algorithm::Md5),
+            hex(&a, Algorithm::Crc32),
+            hex(&a, Algorithm::Blake2b)
+        )
+    );
+
+    // Without -r a directory is an error, but the other files are still hashed
+    let output = run(&["tree", "tree/a.txt"], &dir);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), format!("{}  tree/a.txt\n", hex(&a, Algorithm::Sha256)));
+}
+
+#[test]
+fn json_output() {
+    let dir = make_tree("cli_json");
+    let output = run(&["--json", "-a", "sha1", "tree/a.txt", "missing\"name"], &dir);
+    assert_eq!(output.status.code(), Some(1));
+    let expected = format!(
+        "[\n  {{\"path\": \"tree/a.txt\", \"algorithm\": \"SHA1\", \"digest\": \"{}\"}},\n  {{\"path\": \"missing\\\"name\", \"error\": \"missing\\\"name: ",
+        hex(&dir.join("tree/a.txt"), Algorithm::Sha1)
+    );
+    assert!(stdout(&output).starts_with(&expected), "{}", stdout(&output));
+}
+
+#[test]
+fn reads_stdin() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_file-checksum"))
+        .args(["-a", "md5"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"abc").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(stdout(&output), "900150983cd24fb0d6963f7d28e17f72  -\n");
+}
+
+#[test]
+fn checks_sum_files() {
+    let dir = make_tree("cli_check");
+    let output = run(&["-r", "."], &dir.join("tree"));
+    fs::write(dir.join("tree/SHA256SUMS"), &output.stdout).unwrap();
+
+    let output = run(&["-c", "tree/SHA256SUMS"], &dir);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "./a.txt: OK\n./sub/b.txt: OK\n");
+
+    fs::write(dir.join("tree/a.txt"), b"changed").unwrap();
+    fs::remove_file(dir.join("tree/sub/b.txt")).unwrap();
+    let output = run(&["--check", "--json", "tree/SHA256SUMS"], &dir);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stdout(&output),
+        "[\n  {\"path\": \"./a.txt\", \"status\": \"FAILED\"},\n  {\"path\": \"./sub/b.txt\", \"status\": \"MISSING\"}\n]\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_file_names() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = make_tree("cli_non_utf8");
+    let name = OsStr::from_bytes(b"caf\xe9.txt");
+    fs::write(dir.join(name), b"abc").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_file-checksum")).arg(name).current_dir(&dir).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let mut expected = format!("{}  ", hex(&dir.join(name), Algorithm::Sha256)).into_bytes();
+    expected.extend_from_slice(b"caf\xe9.txt\n");
+    assert_eq!(output.stdout, expected);
+}
+
+#[test]
+fn usage_errors() {
+    let dir = make_tree("cli_usage");
+    assert_eq!(run(&["--bogus"], &dir).status.code(), Some(2));
+    assert_eq!(run(&["-a", "sha3"], &dir).status.code(), Some(2));
+    assert_eq!(run(&["--jobs"], &dir).status.code(), Some(2));
+    assert_eq!(run(&["--tag=yes"], &dir).status.code(), Some(2));
+    assert!(stdout(&run(&["--help"], &dir)).starts_with("Usage: file-checksum"));
+}